@@ -1,7 +1,173 @@
+use std::collections::BTreeMap;
+
 const MAX_RED: u32 = 12;
 const MAX_GREEN: u32 = 13;
 const MAX_BLUE: u32 = 14;
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Color {
+    Red,
+    Green,
+    Blue,
+}
+
+impl From<&str> for Color {
+    fn from(value: &str) -> Self {
+        match value {
+            "red" => Color::Red,
+            "green" => Color::Green,
+            "blue" => Color::Blue,
+            _ => panic!("Unknown color {}", value),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Cubes {
+    red: u32,
+    green: u32,
+    blue: u32,
+}
+
+impl Cubes {
+    fn get(&self, color: Color) -> u32 {
+        match color {
+            Color::Red => self.red,
+            Color::Green => self.green,
+            Color::Blue => self.blue,
+        }
+    }
+
+    fn get_mut(&mut self, color: Color) -> &mut u32 {
+        match color {
+            Color::Red => &mut self.red,
+            Color::Green => &mut self.green,
+            Color::Blue => &mut self.blue,
+        }
+    }
+
+    fn max(self, other: Cubes) -> Cubes {
+        Cubes {
+            red: self.red.max(other.red),
+            green: self.green.max(other.green),
+            blue: self.blue.max(other.blue),
+        }
+    }
+
+    fn contains(&self, draw: &Cubes) -> bool {
+        draw.red <= self.red && draw.green <= self.green && draw.blue <= self.blue
+    }
+}
+
+struct Game {
+    id: u32,
+    draws: Vec<Cubes>,
+}
+
+impl Game {
+    fn is_possible(&self, bag: &Cubes) -> bool {
+        self.draws.iter().all(|draw| bag.contains(draw))
+    }
+
+    fn minimal_bag(&self) -> Cubes {
+        self.draws
+            .iter()
+            .fold(Cubes::default(), |acc, draw| acc.max(*draw))
+    }
+}
+
+type Histogram = BTreeMap<u32, usize>;
+
+#[derive(Debug, PartialEq)]
+struct ColorStats {
+    max: Histogram,
+    mean: Histogram,
+}
+
+fn parse_games(input: &str) -> Vec<Game> {
+    input
+        .lines()
+        .map(|line| {
+            let (game, plays) = line.split_once(": ").expect("Wrong game format");
+            let id = game[5..].parse::<u32>().expect("Game id is not a number");
+            let draws = plays
+                .split("; ")
+                .map(|play| {
+                    play.split(", ").fold(Cubes::default(), |mut draw, cube| {
+                        let (count, color) = cube.split_once(' ').expect("Wrong cube format");
+                        *draw.get_mut(color.into()) += count.parse::<u32>().unwrap();
+                        draw
+                    })
+                })
+                .collect();
+            Game { id, draws }
+        })
+        .collect()
+}
+
+fn minimal_bag(games: &[Game]) -> Cubes {
+    games
+        .iter()
+        .fold(Cubes::default(), |acc, game| acc.max(game.minimal_bag()))
+}
+
+fn possible_games(games: &[Game], bag: &Cubes) -> Vec<u32> {
+    games
+        .iter()
+        .filter(|game| game.is_possible(bag))
+        .map(|game| game.id)
+        .collect()
+}
+
+fn games_impossible_after_decrease(
+    games: &[Game],
+    bag: &Cubes,
+    color: Color,
+    decrease: u32,
+) -> Vec<u32> {
+    let mut smaller_bag = *bag;
+    let count = smaller_bag.get_mut(color);
+    *count = count.saturating_sub(decrease);
+    games
+        .iter()
+        .filter(|game| game.is_possible(bag) && !game.is_possible(&smaller_bag))
+        .map(|game| game.id)
+        .collect()
+}
+
+fn color_stats(games: &[Game], color: Color) -> ColorStats {
+    let mut stats = ColorStats {
+        max: Histogram::new(),
+        mean: Histogram::new(),
+    };
+    for game in games.iter().filter(|game| !game.draws.is_empty()) {
+        let counts = game.draws.iter().map(|draw| draw.get(color));
+        let max = counts.clone().max().unwrap();
+        let mean = counts.sum::<u32>() / game.draws.len() as u32;
+        *stats.max.entry(max).or_default() += 1;
+        *stats.mean.entry(mean).or_default() += 1;
+    }
+    stats
+}
+
+// Bags larger than the minimal bag behave like the minimal bag, so only
+// bags up to it (inclusive) are enumerated.
+fn bags_with_possible_games(games: &[Game], count: usize) -> Vec<Cubes> {
+    let limit = minimal_bag(games);
+    let mut bags = Vec::new();
+    for red in 0..=limit.red {
+        for green in 0..=limit.green {
+            for blue in 0..=limit.blue {
+                let bag = Cubes { red, green, blue };
+                if games.iter().filter(|game| game.is_possible(&bag)).count() == count {
+                    bags.push(bag);
+                }
+            }
+        }
+    }
+    bags
+}
+
 fn first_part(input: &str) -> u32 {
     input
         .lines()
@@ -50,8 +216,48 @@ fn second_part(input: &str) -> u32 {
         .sum()
 }
 
+fn query(games: &[Game], args: &[String]) {
+    let bag = Cubes {
+        red: MAX_RED,
+        green: MAX_GREEN,
+        blue: MAX_BLUE,
+    };
+    match args {
+        [command] if command == "min-bag" => println!("{:?}", minimal_bag(games)),
+        [command] if command == "possible" => println!("{:?}", possible_games(games, &bag)),
+        [command, color, rest @ ..] if command == "decrease" => {
+            let decrease = rest
+                .first()
+                .map(|n| n.parse::<u32>().expect("Decrease is not a number"))
+                .unwrap_or(1);
+            let impossible =
+                games_impossible_after_decrease(games, &bag, color.as_str().into(), decrease);
+            println!("{:?}", impossible);
+        }
+        [command, color] if command == "stats" => {
+            let stats = color_stats(games, color.as_str().into());
+            println!("Max: {:?}", stats.max);
+            println!("Mean: {:?}", stats.mean);
+        }
+        [command, count] if command == "bags" => {
+            let count = count.parse::<usize>().expect("Count is not a number");
+            for bag in bags_with_possible_games(games, count) {
+                println!("{:?}", bag);
+            }
+        }
+        _ => panic!(
+            "Unknown query, use one of: min-bag, possible, decrease <color> [n], stats <color>, bags <n>"
+        ),
+    }
+}
+
 fn main() {
     let input = include_str!("../inputs/input.txt");
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if !args.is_empty() {
+        query(&parse_games(input), &args);
+        return;
+    }
     let first_part = first_part(input);
     println!("First part: {}", first_part);
     let second_part = second_part(input);
@@ -89,4 +295,74 @@ mod tests {
         let result = second_part(data);
         assert_eq!(result, 60948);
     }
+
+    #[test]
+    fn test_minimal_bag() {
+        let data = include_str!("../inputs/test.txt");
+        let games = parse_games(data);
+        let bag = minimal_bag(&games);
+        assert_eq!(
+            bag,
+            Cubes {
+                red: 20,
+                green: 13,
+                blue: 15
+            }
+        );
+        let power: u32 = games
+            .iter()
+            .map(|game| game.minimal_bag())
+            .map(|bag| bag.red * bag.green * bag.blue)
+            .sum();
+        assert_eq!(power, second_part(data));
+    }
+
+    #[test]
+    fn test_possible_games() {
+        let data = include_str!("../inputs/test.txt");
+        let games = parse_games(data);
+        let bag = Cubes {
+            red: MAX_RED,
+            green: MAX_GREEN,
+            blue: MAX_BLUE,
+        };
+        let possible = possible_games(&games, &bag);
+        assert_eq!(possible, vec![1, 2, 5]);
+        assert_eq!(possible.iter().sum::<u32>(), first_part(data));
+        assert!(games_impossible_after_decrease(&games, &bag, Color::Red, 1).is_empty());
+        assert_eq!(
+            games_impossible_after_decrease(&games, &bag, Color::Blue, 9),
+            vec![1]
+        );
+    }
+
+    #[test]
+    fn test_color_stats() {
+        let data = include_str!("../inputs/test.txt");
+        let games = parse_games(data);
+        let stats = color_stats(&games, Color::Red);
+        assert_eq!(
+            stats.max,
+            Histogram::from([(1, 1), (4, 1), (6, 1), (14, 1), (20, 1)])
+        );
+        assert_eq!(
+            stats.mean,
+            Histogram::from([(0, 1), (1, 1), (3, 1), (7, 1), (8, 1)])
+        );
+    }
+
+    #[test]
+    fn test_bags_with_possible_games() {
+        let data = include_str!("../inputs/test.txt");
+        let games = parse_games(data);
+        assert_eq!(
+            bags_with_possible_games(&games, 5),
+            vec![minimal_bag(&games)]
+        );
+        assert!(bags_with_possible_games(&games, 3).contains(&Cubes {
+            red: MAX_RED,
+            green: MAX_GREEN,
+            blue: MAX_BLUE,
+        }));
+    }
 }
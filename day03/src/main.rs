@@ -3,171 +3,285 @@ enum Cell {
     Digit(char),
     Symbol(char),
     Dot,
-    Edge,
 }
 
 struct NumberLocation {
     number: u32,
-    line: usize,
     start: usize,
     end_inclusive: usize,
 }
 
-fn first_part(input: &str) -> u32 {
-    let board = parse_board(input);
-    let board = expand_borad_with_edge(board);
-    let numbers = get_numbers_with_coordinates(&board);
-    let numbers = filter_numbers_without_adjacent_symbols(numbers, &board);
-    numbers.iter().map(|nl| nl.number).sum()
+struct SymbolLocation {
+    symbol: char,
+    line: usize,
+    x: usize,
 }
 
-fn second_part(input: &str) -> u32 {
-    let board = parse_board(input);
-    let board = expand_borad_with_edge(board);
-    let numbers = get_numbers_with_coordinates(&board);
-    let numbers = get_gear_ratios(numbers, &board);
-    numbers.iter().sum()
+#[derive(Clone, Copy)]
+enum Aggregation {
+    Product,
+    Sum,
+    Max,
 }
 
-fn get_gear_ratios(numbers: Vec<NumberLocation>, board: &[Vec<Cell>]) -> Vec<u32> {
-    let mut gears = Vec::new();
-    let stars = board
-        .iter()
-        .enumerate()
-        .flat_map(|(line_number, line)| {
-            line.iter()
-                .enumerate()
-                .filter_map(|(x, cell)| match cell {
-                    Cell::Symbol('*') => Some((line_number, x)),
-                    _ => None,
-                })
-                .collect::<Vec<(usize, usize)>>()
-        })
-        .collect::<Vec<_>>();
-    for (line, x) in stars {
-        let mut first_number_index: Option<usize> = None;
-        for y_shift in -1..=1 {
-            for x_shift in -1..=1 {
-                let (line_number, x) = (
-                    line.checked_add_signed(y_shift).unwrap(),
-                    x.checked_add_signed(x_shift).unwrap(),
-                );
-                let cell: &Cell = &board[line_number][x];
-                if let Cell::Digit(_) = cell {
-                    let number_index = find_number_index(line_number, x, &numbers);
-                    if let Some(index) = first_number_index {
-                        if index == number_index {
-                            continue;
-                        }
-                        gears.push(numbers[index].number * numbers[number_index].number);
-                        break;
-                    } else {
-                        first_number_index = Some(number_index);
-                    }
-                }
+impl From<&str> for Aggregation {
+    fn from(value: &str) -> Self {
+        match value {
+            "product" => Aggregation::Product,
+            "sum" => Aggregation::Sum,
+            "max" => Aggregation::Max,
+            _ => panic!("Unknown aggregation {}", value),
+        }
+    }
+}
+
+struct GearRule {
+    symbol: char,
+    neighbours: usize,
+    aggregation: Aggregation,
+}
+
+const STAR_GEAR: GearRule = GearRule {
+    symbol: '*',
+    neighbours: 2,
+    aggregation: Aggregation::Product,
+};
+
+// rule format: <symbol>:<neighbours>:<aggregation>, e.g. "#:3:sum"
+impl From<&str> for GearRule {
+    fn from(value: &str) -> Self {
+        match value.split(':').collect::<Vec<_>>().as_slice() {
+            [symbol, neighbours, aggregation] if symbol.chars().count() == 1 => GearRule {
+                symbol: symbol.chars().next().unwrap(),
+                neighbours: neighbours
+                    .parse()
+                    .expect("Neighbours count is not a number"),
+                aggregation: (*aggregation).into(),
+            },
+            _ => panic!("Wrong gear rule format {}", value),
+        }
+    }
+}
+
+impl GearRule {
+    // None when the aggregated value doesn't fit into u64
+    fn evaluate(&self, mut numbers: impl Iterator<Item = u32>) -> Option<u64> {
+        match self.aggregation {
+            Aggregation::Product => {
+                numbers.try_fold(1u64, |product, number| product.checked_mul(number as u64))
             }
+            Aggregation::Sum => {
+                numbers.try_fold(0u64, |sum, number| sum.checked_add(number as u64))
+            }
+            Aggregation::Max => Some(numbers.max().unwrap_or(0) as u64),
         }
     }
-    gears
 }
 
-fn find_number_index(line: usize, x: usize, numbers: &[NumberLocation]) -> usize {
-    numbers
-        .iter()
-        .position(|n| n.line == line && n.start <= x && n.end_inclusive >= x)
-        .expect("Every digits need to belogs to number")
+#[derive(Debug, PartialEq)]
+enum GearError {
+    Overflow { line: usize, x: usize },
 }
 
-fn filter_numbers_without_adjacent_symbols(
+const NO_NUMBER: u32 = u32::MAX;
+
+struct Schematic {
     numbers: Vec<NumberLocation>,
-    board: &[Vec<Cell>],
-) -> Vec<NumberLocation> {
-    numbers
-        .into_iter()
-        .filter(|number| {
-            if board[number.line - 1][number.start - 1..number.end_inclusive + 2]
-                .iter()
-                .any(|c| matches!(c, Cell::Symbol(_)))
-            {
-                return true;
-            }
-            if let Cell::Symbol(_) = board[number.line][number.start - 1] {
-                return true;
+    symbols: Vec<SymbolLocation>,
+    // id of the number covering each cell, `NO_NUMBER` elsewhere
+    number_grid: Vec<Vec<u32>>,
+    number_neighbours: Vec<Vec<usize>>,
+    symbol_neighbours: Vec<Vec<usize>>,
+}
+
+impl Schematic {
+    // Parses one line at a time, so only the compact number grid is kept for the whole board.
+    fn parse(input: &str) -> Schematic {
+        let mut numbers = Vec::new();
+        let mut symbols = Vec::new();
+        let mut number_grid = Vec::new();
+        for (line_number, line) in input.lines().enumerate() {
+            let cells = parse_line(line);
+            let mut grid_line = vec![NO_NUMBER; cells.len()];
+            for number in get_numbers_in_line(&cells) {
+                let id = u32::try_from(numbers.len()).expect("Too many numbers");
+                grid_line[number.start..=number.end_inclusive].fill(id);
+                numbers.push(number);
             }
-            if let Cell::Symbol(_) = board[number.line][number.end_inclusive + 1] {
-                return true;
+            symbols.extend(get_symbols_in_line(line_number, &cells));
+            number_grid.push(grid_line);
+        }
+        let mut schematic = Schematic {
+            number_neighbours: vec![Vec::new(); numbers.len()],
+            symbol_neighbours: Vec::with_capacity(symbols.len()),
+            numbers,
+            symbols,
+            number_grid,
+        };
+        for (symbol_id, symbol) in schematic.symbols.iter().enumerate() {
+            let neighbours = schematic.numbers_around(symbol.line, symbol.x);
+            for &number_id in &neighbours {
+                schematic.number_neighbours[number_id].push(symbol_id);
             }
-            if board[number.line + 1][number.start - 1..number.end_inclusive + 2]
-                .iter()
-                .any(|c| matches!(c, Cell::Symbol(_)))
-            {
-                return true;
+            schematic.symbol_neighbours.push(neighbours);
+        }
+        schematic
+    }
+
+    fn number_id_at(&self, line: usize, x: usize) -> Option<usize> {
+        match *self.number_grid.get(line)?.get(x)? {
+            NO_NUMBER => None,
+            id => Some(id as usize),
+        }
+    }
+
+    fn numbers_around(&self, line: usize, x: usize) -> Vec<usize> {
+        let mut neighbours = Vec::new();
+        for line in line.saturating_sub(1)..=line + 1 {
+            for x in x.saturating_sub(1)..=x + 1 {
+                if let Some(number_id) = self.number_id_at(line, x) {
+                    if !neighbours.contains(&number_id) {
+                        neighbours.push(number_id);
+                    }
+                }
             }
-            false
+        }
+        neighbours
+    }
+
+    fn part_numbers(&self) -> impl Iterator<Item = &NumberLocation> {
+        self.numbers
+            .iter()
+            .zip(&self.number_neighbours)
+            .filter(|(_, symbols)| !symbols.is_empty())
+            .map(|(number, _)| number)
+    }
+
+    fn symbols_with_neighbours(
+        &self,
+        min_neighbours: usize,
+    ) -> impl Iterator<Item = (&SymbolLocation, &[usize])> {
+        self.symbols
+            .iter()
+            .zip(&self.symbol_neighbours)
+            .filter(move |(_, numbers)| numbers.len() >= min_neighbours)
+            .map(|(symbol, numbers)| (symbol, numbers.as_slice()))
+    }
+
+    fn gear_values(&self, rules: &[GearRule]) -> Result<Vec<u64>, GearError> {
+        self.symbols
+            .iter()
+            .zip(&self.symbol_neighbours)
+            .filter_map(|(symbol, numbers)| {
+                let rule = rules.iter().find(|rule| {
+                    rule.symbol == symbol.symbol && rule.neighbours == numbers.len()
+                })?;
+                let value = rule.evaluate(numbers.iter().map(|&id| self.numbers[id].number));
+                Some(value.ok_or(GearError::Overflow {
+                    line: symbol.line,
+                    x: symbol.x,
+                }))
+            })
+            .collect()
+    }
+}
+
+fn first_part(input: &str) -> u32 {
+    let schematic = Schematic::parse(input);
+    schematic.part_numbers().map(|nl| nl.number).sum()
+}
+
+fn sum_gears(schematic: &Schematic, rules: &[GearRule]) -> (usize, u64) {
+    let gears = schematic
+        .gear_values(rules)
+        .unwrap_or_else(|err| panic!("Can't evaluate gear: {:?}", err));
+    let sum = gears
+        .iter()
+        .try_fold(0u64, |sum, &gear| sum.checked_add(gear))
+        .expect("Sum of gears doesn't fit into u64");
+    (gears.len(), sum)
+}
+
+fn second_part(input: &str) -> u64 {
+    let schematic = Schematic::parse(input);
+    sum_gears(&schematic, &[STAR_GEAR]).1
+}
+
+fn get_symbols_in_line(line_number: usize, line: &[Cell]) -> Vec<SymbolLocation> {
+    line.iter()
+        .enumerate()
+        .filter_map(|(x, cell)| match cell {
+            Cell::Symbol(symbol) => Some(SymbolLocation {
+                symbol: *symbol,
+                line: line_number,
+                x,
+            }),
+            _ => None,
         })
         .collect()
 }
 
-fn get_numbers_with_coordinates(board: &[Vec<Cell>]) -> Vec<NumberLocation> {
+fn get_numbers_in_line(line: &[Cell]) -> Vec<NumberLocation> {
     let mut numbers = Vec::new();
-    board.iter().enumerate().for_each(|(line_number, line)| {
-        let mut start = None;
-        let mut number = 0u32;
-        for (x, cell) in line.iter().enumerate() {
-            match cell {
-                Cell::Digit(d) => {
-                    number = number * 10 + d.to_digit(10).unwrap();
-                    start = start.or(Some(x));
-                }
-                Cell::Dot | Cell::Symbol(_) | Cell::Edge if start.is_some() => {
-                    numbers.push(NumberLocation {
-                        number,
-                        line: line_number,
-                        start: start.unwrap(),
-                        end_inclusive: x - 1,
-                    });
-                    start = None;
-                    number = 0;
-                }
-                Cell::Dot | Cell::Symbol(_) | Cell::Edge => continue,
+    let mut start = None;
+    let mut number = 0u32;
+    for (x, cell) in line.iter().enumerate() {
+        match cell {
+            Cell::Digit(d) => {
+                number = number * 10 + d.to_digit(10).unwrap();
+                start = start.or(Some(x));
+            }
+            Cell::Dot | Cell::Symbol(_) if start.is_some() => {
+                numbers.push(NumberLocation {
+                    number,
+                    start: start.unwrap(),
+                    end_inclusive: x - 1,
+                });
+                start = None;
+                number = 0;
             }
+            Cell::Dot | Cell::Symbol(_) => continue,
         }
-    });
+    }
+    if let Some(start) = start {
+        numbers.push(NumberLocation {
+            number,
+            start,
+            end_inclusive: line.len() - 1,
+        });
+    }
     numbers
 }
 
-fn expand_borad_with_edge(board: Vec<Vec<Cell>>) -> Vec<Vec<Cell>> {
-    let mut new_board = Vec::with_capacity(board.capacity() + 2);
-    let length = board.get(0).map(|line| line.len()).unwrap_or(0);
-    new_board.push(vec![Cell::Edge; length + 2]);
-    board
-        .into_iter()
-        .for_each(|line| new_board.push([vec![Cell::Edge], line, vec![Cell::Edge]].concat()));
-    new_board.push(vec![Cell::Edge; length + 2]);
-    new_board
-}
-
-fn parse_board(input: &str) -> Vec<Vec<Cell>> {
-    input
-        .lines()
-        .map(|line| {
-            line.chars()
-                .map(|c| match c {
-                    c if c.is_ascii_digit() => Cell::Digit(c),
-                    '.' => Cell::Dot,
-                    c => Cell::Symbol(c),
-                })
-                .collect::<Vec<_>>()
+fn parse_line(line: &str) -> Vec<Cell> {
+    line.chars()
+        .map(|c| match c {
+            c if c.is_ascii_digit() => Cell::Digit(c),
+            '.' => Cell::Dot,
+            c => Cell::Symbol(c),
         })
-        .collect::<Vec<_>>()
+        .collect()
 }
 
 fn main() {
     let input = include_str!("../inputs/input.txt");
+    let rules = std::env::args()
+        .skip(1)
+        .map(|rule| rule.as_str().into())
+        .collect::<Vec<GearRule>>();
+    if !rules.is_empty() {
+        let schematic = Schematic::parse(input);
+        let (count, sum) = sum_gears(&schematic, &rules);
+        println!("Gears: {}, sum: {}", count, sum);
+        return;
+    }
     let first_part = first_part(input);
     println!("First part: {}", first_part);
     let second_part = second_part(input);
     println!("Second part: {}", second_part);
+    let schematic = Schematic::parse(input);
+    let crowded = schematic.symbols_with_neighbours(3).count();
+    println!("Symbols with 3+ numbers: {}", crowded);
 }
 
 #[cfg(test)]
@@ -209,4 +323,105 @@ mod tests {
         let result = second_part(data);
         assert_eq!(result, 81463996);
     }
+
+    #[test]
+    fn test_schematic_graph() {
+        let data = include_str!("../inputs/test.txt");
+        let schematic = Schematic::parse(data);
+        assert_eq!(schematic.numbers.len(), 10);
+        assert_eq!(schematic.part_numbers().count(), 8);
+        let gears = schematic
+            .symbols_with_neighbours(2)
+            .map(|(symbol, numbers)| {
+                let mut numbers = numbers
+                    .iter()
+                    .map(|&id| schematic.numbers[id].number)
+                    .collect::<Vec<_>>();
+                numbers.sort_unstable();
+                (symbol.symbol, numbers)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(gears, vec![('*', vec![35, 467]), ('*', vec![598, 755])]);
+        assert_eq!(schematic.symbols_with_neighbours(3).count(), 0);
+    }
+
+    #[test]
+    fn test_number_id_at() {
+        let data = include_str!("../inputs/test.txt");
+        let schematic = Schematic::parse(data);
+        let id = schematic.number_id_at(0, 2).unwrap();
+        assert_eq!(schematic.numbers[id].number, 467);
+        assert_eq!(schematic.number_id_at(0, 0), Some(id));
+        assert_eq!(schematic.number_id_at(0, 3), None);
+        assert_eq!(schematic.number_id_at(100, 100), None);
+    }
+
+    #[test]
+    fn test_gear_rules() {
+        let data = include_str!("../inputs/test.txt");
+        let schematic = Schematic::parse(data);
+        assert_eq!(
+            schematic.gear_values(&["#:1:sum".into()]).unwrap(),
+            vec![633]
+        );
+        assert_eq!(
+            schematic.gear_values(&["*:1:max".into()]).unwrap(),
+            vec![617]
+        );
+        let rules = [STAR_GEAR, "$:1:product".into()];
+        let mut values = schematic.gear_values(&rules).unwrap();
+        values.sort_unstable();
+        assert_eq!(values, vec![664, 16345, 451490]);
+    }
+
+    #[test]
+    fn test_gear_rules_too_many_neighbours() {
+        let data = "1.2\n.*.\n3..";
+        let schematic = Schematic::parse(data);
+        assert!(schematic.gear_values(&[STAR_GEAR]).unwrap().is_empty());
+        assert_eq!(schematic.gear_values(&["*:3:sum".into()]).unwrap(), vec![6]);
+        assert_eq!(
+            schematic.gear_values(&["*:3:product".into()]).unwrap(),
+            vec![6]
+        );
+        assert_eq!(schematic.gear_values(&["*:3:max".into()]).unwrap(), vec![3]);
+    }
+
+    #[test]
+    fn test_gear_rules_overflow() {
+        let schematic = Schematic::parse("999.999\n...#...\n999.999");
+        let product = schematic.gear_values(&["#:4:product".into()]);
+        assert_eq!(product, Ok(vec![999u64.pow(4)]));
+        let data = "4000000000.4000000000.4000000000\n\
+                    ..........#.....................\n\
+                    4000000000.4000000000.4000000000";
+        let schematic = Schematic::parse(data);
+        assert_eq!(
+            schematic.gear_values(&["#:4:product".into()]),
+            Err(GearError::Overflow { line: 1, x: 10 })
+        );
+        assert_eq!(
+            schematic.gear_values(&["#:4:sum".into()]),
+            Ok(vec![16000000000])
+        );
+    }
+
+    #[test]
+    fn test_ragged_board_edges() {
+        let data = "12*\n.\n3#..45\n..\n....*6";
+        let schematic = Schematic::parse(data);
+        let numbers = schematic
+            .numbers
+            .iter()
+            .map(|n| n.number)
+            .collect::<Vec<_>>();
+        assert_eq!(numbers, vec![12, 3, 45, 6]);
+        let parts = schematic
+            .part_numbers()
+            .map(|n| n.number)
+            .collect::<Vec<_>>();
+        assert_eq!(parts, vec![12, 3, 6]);
+        assert_eq!(schematic.number_id_at(1, 1), None);
+        assert_eq!(schematic.number_id_at(2, 5), Some(2));
+    }
 }
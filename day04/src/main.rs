@@ -1,12 +1,119 @@
+use std::collections::{BTreeMap, HashSet};
+
 struct Card {
-    index: usize,
-    winning_numbers: Vec<usize>,
+    id: usize,
+    winning_numbers: HashSet<usize>,
     numbers: Vec<usize>,
 }
 
-struct Win {
-    _index: usize,
-    wins: usize,
+impl Card {
+    fn matches(&self) -> usize {
+        self.numbers
+            .iter()
+            .filter(|num| self.winning_numbers.contains(num))
+            .count()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum CardTableError {
+    Empty,
+    DuplicateId(usize),
+    MissingId(usize),
+}
+
+struct CardTable {
+    cards: BTreeMap<usize, Card>,
+}
+
+impl CardTable {
+    fn new(cards: Vec<Card>) -> Result<CardTable, CardTableError> {
+        let mut table = BTreeMap::new();
+        for card in cards {
+            if let Some(card) = table.insert(card.id, card) {
+                return Err(CardTableError::DuplicateId(card.id));
+            }
+        }
+        let (first, last) = match (table.keys().next(), table.keys().next_back()) {
+            (Some(&first), Some(&last)) => (first, last),
+            _ => return Err(CardTableError::Empty),
+        };
+        if let Some(missing) = (first..=last).find(|id| !table.contains_key(id)) {
+            return Err(CardTableError::MissingId(missing));
+        }
+        Ok(CardTable { cards: table })
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &Card> {
+        self.cards.values()
+    }
+}
+
+enum Scoring {
+    Doubling,
+    Linear,
+    Fibonacci,
+    Custom(Box<dyn Fn(usize) -> u32>),
+}
+
+impl From<&str> for Scoring {
+    fn from(value: &str) -> Self {
+        match value.split_once(':') {
+            None if value == "doubling" => Scoring::Doubling,
+            None if value == "linear" => Scoring::Linear,
+            None if value == "fibonacci" => Scoring::Fibonacci,
+            Some(("power", exponent)) => {
+                let exponent = exponent.parse::<u32>().expect("Exponent is not a number");
+                Scoring::Custom(Box::new(move |wins| {
+                    u32::try_from(wins)
+                        .ok()
+                        .and_then(|wins| wins.checked_pow(exponent))
+                        .unwrap_or_else(|| panic!("Score for {} wins doesn't fit into u32", wins))
+                }))
+            }
+            _ => panic!("Unknown scoring {}", value),
+        }
+    }
+}
+
+impl Scoring {
+    fn score(&self, wins: usize) -> u32 {
+        let score = match self {
+            _ if wins == 0 => Some(0),
+            Scoring::Doubling => u32::try_from(wins - 1)
+                .ok()
+                .and_then(|shift| 1u32.checked_shl(shift)),
+            Scoring::Linear => u32::try_from(wins).ok(),
+            Scoring::Fibonacci => (1..wins)
+                .try_fold((0u32, 1u32), |(a, b), _| Some((b, a.checked_add(b)?)))
+                .map(|(_, b)| b),
+            Scoring::Custom(score) => return score(wins),
+        };
+        score.unwrap_or_else(|| panic!("Score for {} wins doesn't fit into u32", wins))
+    }
+}
+
+#[derive(Clone, Copy)]
+enum CascadePolicy {
+    Clamp,
+    Error,
+}
+
+#[derive(Debug, PartialEq)]
+enum CascadeError {
+    OutOfRange { card: usize, wins: usize },
+}
+
+#[derive(Debug, PartialEq)]
+struct CopyRecord {
+    source: usize,
+    target: usize,
+    copies: u32,
+}
+
+struct Cascade {
+    copies: BTreeMap<usize, u32>,
+    trace: Option<Vec<CopyRecord>>,
 }
 
 fn get_numbers(text: &str) -> Vec<usize> {
@@ -15,21 +122,21 @@ fn get_numbers(text: &str) -> Vec<usize> {
         .collect()
 }
 
-fn parse_cards(input: &str) -> Vec<Card> {
+fn parse_card_list(input: &str) -> Vec<Card> {
     input
         .lines()
         .map(|line| {
             let colon_index = line.find(':').unwrap();
-            let index = line[..colon_index]
+            let id = line[..colon_index]
                 .split_ascii_whitespace()
                 .collect::<Vec<_>>()[1]
                 .parse::<usize>()
                 .unwrap();
             let (winning, rest) = line[colon_index + 2..].split_once('|').unwrap();
-            let winning_numbers = get_numbers(winning);
+            let winning_numbers = get_numbers(winning).into_iter().collect();
             let numbers = get_numbers(rest);
             Card {
-                index,
+                id,
                 winning_numbers,
                 numbers,
             }
@@ -37,43 +144,63 @@ fn parse_cards(input: &str) -> Vec<Card> {
         .collect()
 }
 
-fn first_part(cards: &[Card]) -> u32 {
-    cards
-        .iter()
-        .filter_map(|card| {
-            card.numbers.iter().fold(None, |acc, num| {
-                if card.winning_numbers.contains(num) {
-                    return acc.map(|i| i << 1).or(Some(1));
-                }
-                acc
-            })
-        })
-        .sum()
+fn parse_cards(input: &str) -> CardTable {
+    CardTable::new(parse_card_list(input))
+        .unwrap_or_else(|err| panic!("Invalid card table: {:?}", err))
 }
 
-fn second_part(cards: &[Card]) -> u32 {
-    let wins = cards
-        .iter()
-        .map(|card| {
-            let wins = card.numbers.iter().fold(0, |acc, num| {
-                if card.winning_numbers.contains(num) {
-                    return acc + 1;
+fn cascade(cards: &CardTable, policy: CascadePolicy, trace: bool) -> Result<Cascade, CascadeError> {
+    let mut copies = cards
+        .cards
+        .keys()
+        .map(|&id| (id, 1))
+        .collect::<BTreeMap<_, _>>();
+    let mut records = Vec::new();
+    for card in cards.iter() {
+        let wins = card.matches();
+        let source_copies = copies[&card.id];
+        for target in card.id + 1..=card.id + wins {
+            let Some(target_copies) = copies.get_mut(&target) else {
+                match policy {
+                    CascadePolicy::Clamp => break,
+                    CascadePolicy::Error => {
+                        return Err(CascadeError::OutOfRange {
+                            card: card.id,
+                            wins,
+                        })
+                    }
                 }
-                acc
-            });
-            Win {
-                _index: card.index,
-                wins,
+            };
+            *target_copies += source_copies;
+            if trace {
+                records.push(CopyRecord {
+                    source: card.id,
+                    target,
+                    copies: source_copies,
+                });
             }
-        })
-        .collect::<Vec<_>>();
-    let mut multiply = vec![1; wins.len()];
-    for (index, win) in wins.iter().enumerate() {
-        for i in index + 1..index + 1 + win.wins {
-            multiply[i] += multiply[index];
         }
     }
-    multiply.iter().sum()
+    Ok(Cascade {
+        copies,
+        trace: trace.then_some(records),
+    })
+}
+
+fn score_cards(cards: &CardTable, scoring: &Scoring) -> u32 {
+    cards.iter().map(|card| scoring.score(card.matches())).sum()
+}
+
+fn first_part(cards: &CardTable) -> u32 {
+    score_cards(cards, &Scoring::Doubling)
+}
+
+fn second_part(cards: &CardTable) -> u32 {
+    cascade(cards, CascadePolicy::Error, false)
+        .expect("Card wins copies past the end of the table")
+        .copies
+        .values()
+        .sum()
 }
 
 fn main() {
@@ -83,6 +210,24 @@ fn main() {
     println!("First part: {}", first_part);
     let second_part = second_part(&cards);
     println!("Second part: {}", second_part);
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if let Some(scoring) = args.iter().find_map(|arg| arg.strip_prefix("score=")) {
+        let score = score_cards(&cards, &scoring.into());
+        println!("Score ({}): {}", scoring, score);
+    }
+    if args.iter().any(|arg| arg == "trace") {
+        let policy = match args.iter().any(|arg| arg == "clamp") {
+            true => CascadePolicy::Clamp,
+            false => CascadePolicy::Error,
+        };
+        let cascade = cascade(&cards, policy, true).expect("Unable to trace cascade");
+        for record in cascade.trace.unwrap() {
+            println!(
+                "Card {} -> card {}: {} copies",
+                record.source, record.target, record.copies
+            );
+        }
+    }
 }
 
 #[cfg(test)]
@@ -120,4 +265,95 @@ mod tests {
         let result = second_part(&cards);
         assert_eq!(result, 12648035);
     }
+
+    #[test]
+    fn test_cascade_trace() {
+        let data = include_str!("../inputs/test.txt");
+        let cards = parse_cards(data);
+        let cascade = cascade(&cards, CascadePolicy::Error, true).unwrap();
+        let copies = cascade.copies.values().copied().collect::<Vec<_>>();
+        assert_eq!(copies, vec![1, 2, 4, 8, 14, 1]);
+        let trace = cascade.trace.unwrap();
+        assert_eq!(trace.iter().filter(|r| r.source == 1).count(), 4);
+        assert_eq!(
+            trace.iter().find(|r| r.source == 3 && r.target == 5),
+            Some(&CopyRecord {
+                source: 3,
+                target: 5,
+                copies: 4
+            })
+        );
+        let to_fifth = trace.iter().filter(|r| r.target == 5).map(|r| r.copies);
+        assert_eq!(to_fifth.sum::<u32>() + 1, cascade.copies[&5]);
+    }
+
+    #[test]
+    fn test_cascade_out_of_range() {
+        let data = "Card 1: 1 2 | 1 9\nCard 2: 3 4 | 3 5";
+        let cards = parse_cards(data);
+        assert!(matches!(
+            cascade(&cards, CascadePolicy::Error, false),
+            Err(CascadeError::OutOfRange { card: 2, wins: 1 })
+        ));
+        let cascade = cascade(&cards, CascadePolicy::Clamp, false).unwrap();
+        assert_eq!(cascade.copies, BTreeMap::from([(1, 1), (2, 2)]));
+        assert!(cascade.trace.is_none());
+    }
+
+    #[test]
+    fn test_scoring() {
+        let data = include_str!("../inputs/test.txt");
+        let cards = parse_cards(data);
+        assert_eq!(score_cards(&cards, &Scoring::Doubling), 13);
+        assert_eq!(score_cards(&cards, &Scoring::Linear), 4 + 2 + 2 + 1);
+        assert_eq!(score_cards(&cards, &Scoring::Fibonacci), 3 + 1 + 1 + 1);
+        let squares = Scoring::Custom(Box::new(|wins| (wins * wins) as u32));
+        assert_eq!(score_cards(&cards, &squares), 16 + 4 + 4 + 1);
+        assert_eq!(score_cards(&cards, &"power:2".into()), 16 + 4 + 4 + 1);
+        let fibonacci = (0..7).map(|wins| Scoring::Fibonacci.score(wins));
+        assert_eq!(fibonacci.collect::<Vec<_>>(), vec![0, 1, 1, 2, 3, 5, 8]);
+        assert_eq!(Scoring::Doubling.score(32), 1 << 31);
+        assert_eq!(Scoring::Fibonacci.score(47), 2971215073);
+    }
+
+    #[test]
+    #[should_panic(expected = "Score for 33 wins doesn't fit into u32")]
+    fn test_doubling_score_overflow() {
+        Scoring::Doubling.score(33);
+    }
+
+    #[test]
+    #[should_panic(expected = "Score for 48 wins doesn't fit into u32")]
+    fn test_fibonacci_score_overflow() {
+        Scoring::Fibonacci.score(48);
+    }
+
+    #[test]
+    #[should_panic(expected = "Score for 70000 wins doesn't fit into u32")]
+    fn test_power_score_overflow() {
+        Scoring::from("power:2").score(70000);
+    }
+
+    #[test]
+    fn test_card_table_validation() {
+        let data = "Card 3: 1 | 1\nCard 2: 1 | 2";
+        let cards = parse_cards(data);
+        assert_eq!(cards.iter().map(|c| c.id).collect::<Vec<_>>(), vec![2, 3]);
+        let cascade = cascade(&cards, CascadePolicy::Clamp, false).unwrap();
+        assert_eq!(cascade.copies, BTreeMap::from([(2, 1), (3, 1)]));
+        let duplicate = parse_card_list("Card 1: 1 | 1\nCard 1: 1 | 2");
+        assert_eq!(
+            CardTable::new(duplicate).err(),
+            Some(CardTableError::DuplicateId(1))
+        );
+        let gap = parse_card_list("Card 1: 1 | 1\nCard 4: 1 | 2\nCard 2: 1 | 2");
+        assert_eq!(
+            CardTable::new(gap).err(),
+            Some(CardTableError::MissingId(3))
+        );
+        assert_eq!(
+            CardTable::new(Vec::new()).err(),
+            Some(CardTableError::Empty)
+        );
+    }
 }
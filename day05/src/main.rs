@@ -1,59 +1,317 @@
-#[derive(PartialEq, Clone, Debug)]
-enum PlantStep {
-    Seed,
-    Soil,
-    Fertilizer,
-    Water,
-    Light,
-    Temperature,
-    Humidity,
-    Location,
-}
-
-impl From<&str> for PlantStep {
-    fn from(value: &str) -> Self {
-        match value {
-            "seed" => PlantStep::Seed,
-            "soil" => PlantStep::Soil,
-            "fertilizer" => PlantStep::Fertilizer,
-            "water" => PlantStep::Water,
-            "light" => PlantStep::Light,
-            "temperature" => PlantStep::Temperature,
-            "humidity" => PlantStep::Humidity,
-            "location" => PlantStep::Location,
-            _ => unimplemented!(),
-        }
-    }
-}
-
-#[derive(Debug)]
+use std::collections::HashMap;
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+struct Category(usize);
+
+#[derive(Default)]
+struct Categories {
+    names: Vec<String>,
+    ids: HashMap<String, Category>,
+}
+
+impl Categories {
+    fn intern(&mut self, name: &str) -> Category {
+        if let Some(&category) = self.ids.get(name) {
+            return category;
+        }
+        let category = Category(self.names.len());
+        self.names.push(name.to_string());
+        self.ids.insert(name.to_string(), category);
+        category
+    }
+
+    fn get(&self, name: &str) -> Option<Category> {
+        self.ids.get(name).copied()
+    }
+
+    fn name(&self, category: Category) -> &str {
+        &self.names[category.0]
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum AlmanacError {
+    UnknownCategory(String),
+    NoPath(String, String),
+    AmbiguousPath(String, String),
+    Cycle(Vec<String>),
+}
+
+// Set of half-open ranges kept sorted, disjoint and coalesced.
+#[derive(Debug, Clone, PartialEq, Default)]
+struct RangeSet<T> {
+    ranges: Vec<(T, T)>,
+}
+
+// Operations allowed to be dead outside of tests are only exercised by the tests,
+// the puzzle itself builds sets from ranges and intersects them.
+impl<T: Copy + Ord> RangeSet<T> {
+    #[cfg_attr(not(test), allow(dead_code))]
+    fn new() -> RangeSet<T> {
+        RangeSet { ranges: Vec::new() }
+    }
+
+    fn from_ranges(ranges: impl IntoIterator<Item = (T, T)>) -> RangeSet<T> {
+        let mut ranges = ranges
+            .into_iter()
+            .filter(|(start, end)| start < end)
+            .collect::<Vec<_>>();
+        ranges.sort_unstable();
+        let ranges =
+            ranges
+                .into_iter()
+                .fold(Vec::new(), |mut merged: Vec<(T, T)>, (start, end)| {
+                    match merged.last_mut() {
+                        Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                        _ => merged.push((start, end)),
+                    }
+                    merged
+                });
+        RangeSet { ranges }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &(T, T)> {
+        self.ranges.iter()
+    }
+
+    #[cfg_attr(not(test), allow(dead_code))]
+    fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    fn min(&self) -> Option<T> {
+        self.ranges.first().map(|range| range.0)
+    }
+
+    #[cfg_attr(not(test), allow(dead_code))]
+    fn contains(&self, value: T) -> bool {
+        let index = self.ranges.partition_point(|range| range.1 <= value);
+        self.ranges.get(index).is_some_and(|range| range.0 <= value)
+    }
+
+    #[cfg_attr(not(test), allow(dead_code))]
+    fn union(&self, other: &RangeSet<T>) -> RangeSet<T> {
+        RangeSet::from_ranges(self.iter().chain(other.iter()).copied())
+    }
+
+    fn intersection(&self, other: &RangeSet<T>) -> RangeSet<T> {
+        let mut ranges = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while let (Some(a), Some(b)) = (self.ranges.get(i), other.ranges.get(j)) {
+            let (start, end) = (a.0.max(b.0), a.1.min(b.1));
+            if start < end {
+                ranges.push((start, end));
+            }
+            if a.1 < b.1 {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+        RangeSet { ranges }
+    }
+
+    #[cfg_attr(not(test), allow(dead_code))]
+    fn difference(&self, other: &RangeSet<T>) -> RangeSet<T> {
+        let mut ranges = Vec::new();
+        let mut others = other.ranges.iter().peekable();
+        for &(mut start, end) in &self.ranges {
+            while let Some(&&(other_start, other_end)) = others.peek() {
+                if other_end <= start {
+                    others.next();
+                    continue;
+                }
+                if other_start >= end {
+                    break;
+                }
+                if other_start > start {
+                    ranges.push((start, other_start));
+                }
+                if other_end >= end {
+                    start = end;
+                    break;
+                }
+                start = other_end;
+                others.next();
+            }
+            if start < end {
+                ranges.push((start, end));
+            }
+        }
+        RangeSet { ranges }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 struct Interval {
     from: u64,
     to_exclusive: u64,
     shift: i64,
-    len: u64,
 }
 
-#[derive(Debug)]
+impl Interval {
+    fn image(&self) -> (u64, u64) {
+        (
+            self.from.checked_add_signed(self.shift).unwrap(),
+            self.to_exclusive.checked_add_signed(self.shift).unwrap(),
+        )
+    }
+}
+
+// Piecewise shift function over [0, u64::MAX). Intervals are sorted, do not
+// overlap, leave no gaps and neighbours never share the same shift.
+#[derive(Debug, Clone, PartialEq)]
 struct Mapping {
-    form: PlantStep,
-    to: PlantStep,
+    form: Category,
+    to: Category,
     intervals: Vec<Interval>,
 }
 
 struct Plan {
     seeds: Vec<u64>,
+    categories: Categories,
     mappings: Vec<Mapping>,
 }
 
 impl Mapping {
+    // When input intervals overlap the first one wins, same as a linear search.
+    fn new(form: Category, to: Category, intervals: &[Interval]) -> Mapping {
+        let mut breakpoints = intervals
+            .iter()
+            .flat_map(|interval| [interval.from, interval.to_exclusive])
+            .chain([0, u64::MAX])
+            .collect::<Vec<_>>();
+        breakpoints.sort_unstable();
+        breakpoints.dedup();
+        let pieces = breakpoints
+            .windows(2)
+            .map(|w| Interval {
+                from: w[0],
+                to_exclusive: w[1],
+                shift: intervals
+                    .iter()
+                    .find(|interval| w[0] >= interval.from && w[0] < interval.to_exclusive)
+                    .map(|interval| interval.shift)
+                    .unwrap_or(0),
+            })
+            .collect();
+        Mapping {
+            form,
+            to,
+            intervals: normalize(pieces),
+        }
+    }
+
     fn map(&self, input: u64) -> u64 {
+        let index = self
+            .intervals
+            .partition_point(|interval| interval.to_exclusive <= input);
         self.intervals
-            .iter()
-            .find(|interval| input >= interval.from && input < interval.to_exclusive)
+            .get(index)
             .map(|interval| input.checked_add_signed(interval.shift).unwrap())
             .unwrap_or(input)
     }
+
+    fn then(&self, next: &Mapping) -> Mapping {
+        let mut pieces = Vec::new();
+        for interval in &self.intervals {
+            let (start, end) = interval.image();
+            for next_interval in &next.intervals {
+                let (from, to) = (
+                    start.max(next_interval.from),
+                    end.min(next_interval.to_exclusive),
+                );
+                if from < to {
+                    pieces.push(Interval {
+                        from: from.checked_add_signed(-interval.shift).unwrap(),
+                        to_exclusive: to.checked_add_signed(-interval.shift).unwrap(),
+                        shift: interval.shift + next_interval.shift,
+                    });
+                }
+            }
+        }
+        pieces.sort_unstable_by_key(|interval| interval.from);
+        Mapping {
+            form: self.form,
+            to: next.to,
+            intervals: normalize(pieces),
+        }
+    }
+
+    // Only bijections can be inverted, so overlapping or missing images give None.
+    fn invert(&self) -> Option<Mapping> {
+        let mut pieces = self
+            .intervals
+            .iter()
+            .map(|interval| {
+                let (from, to_exclusive) = interval.image();
+                Interval {
+                    from,
+                    to_exclusive,
+                    shift: -interval.shift,
+                }
+            })
+            .collect::<Vec<_>>();
+        pieces.sort_unstable_by_key(|interval| interval.from);
+        let gap_free = pieces.first()?.from == 0
+            && pieces.last()?.to_exclusive == u64::MAX
+            && pieces.windows(2).all(|w| w[0].to_exclusive == w[1].from);
+        gap_free.then(|| Mapping {
+            form: self.to,
+            to: self.form,
+            intervals: normalize(pieces),
+        })
+    }
+
+    fn map_ranges(&self, ranges: &RangeSet<u64>) -> RangeSet<u64> {
+        let mut mapped = Vec::new();
+        for &(start, end) in ranges.iter() {
+            let first = self
+                .intervals
+                .partition_point(|interval| interval.to_exclusive <= start);
+            for interval in self.intervals[first..]
+                .iter()
+                .take_while(|interval| interval.from < end)
+            {
+                let (from, to) = (start.max(interval.from), end.min(interval.to_exclusive));
+                mapped.push((
+                    from.checked_add_signed(interval.shift).unwrap(),
+                    to.checked_add_signed(interval.shift).unwrap(),
+                ));
+            }
+        }
+        RangeSet::from_ranges(mapped)
+    }
+
+    fn preimage(&self, ranges: &RangeSet<u64>) -> RangeSet<u64> {
+        let mut preimage = Vec::new();
+        for interval in &self.intervals {
+            let image = RangeSet::from_ranges([interval.image()]);
+            for &(start, end) in image.intersection(ranges).iter() {
+                preimage.push((
+                    start.checked_add_signed(-interval.shift).unwrap(),
+                    end.checked_add_signed(-interval.shift).unwrap(),
+                ));
+            }
+        }
+        RangeSet::from_ranges(preimage)
+    }
+}
+
+fn normalize(intervals: Vec<Interval>) -> Vec<Interval> {
+    intervals
+        .into_iter()
+        .fold(Vec::new(), |mut merged: Vec<Interval>, interval| {
+            match merged.last_mut() {
+                Some(last)
+                    if last.shift == interval.shift && last.to_exclusive == interval.from =>
+                {
+                    last.to_exclusive = interval.to_exclusive
+                }
+                _ => merged.push(interval),
+            }
+            merged
+        })
 }
 
 fn parse_plan(input: &str) -> Plan {
@@ -80,6 +338,7 @@ fn parse_plan(input: &str) -> Plan {
         vec
     });
     maps.push(last);
+    let mut categories = Categories::default();
     let maps = maps
         .iter()
         .map(|lines| {
@@ -98,97 +357,194 @@ fn parse_plan(input: &str) -> Plan {
                                 + int.parse::<u64>().unwrap(),
                             shift: (map_to.parse::<i64>().unwrap()
                                 - map_from.parse::<i64>().unwrap()),
-                            len: int.parse::<u64>().unwrap(),
                         },
                         _ => unimplemented!(),
                     },
                 )
-                .collect();
-            Mapping {
-                form: from.into(),
-                to: to.into(),
-                intervals: numbers,
-            }
+                .collect::<Vec<_>>();
+            let (from, to) = (categories.intern(from), categories.intern(to));
+            Mapping::new(from, to, &numbers)
         })
         .collect();
     Plan {
         seeds,
+        categories,
         mappings: maps,
     }
 }
 
-fn first_part(input: &Plan) -> u64 {
-    let mut step = PlantStep::Seed;
-    let mut numbers = input.seeds.clone();
-    while step != PlantStep::Location {
-        let map = input
+impl Plan {
+    fn category(&self, name: &str) -> Result<Category, AlmanacError> {
+        self.categories
+            .get(name)
+            .ok_or_else(|| AlmanacError::UnknownCategory(name.to_string()))
+    }
+
+    fn check_cycles(&self) -> Result<(), AlmanacError> {
+        // 0 = not visited, 1 = on current path, 2 = done
+        let mut state = vec![0u8; self.categories.names.len()];
+        let mut path = Vec::new();
+        for start in 0..state.len() {
+            if let Some(cycle) = self.find_cycle(Category(start), &mut state, &mut path) {
+                let names = cycle.iter().map(|&c| self.categories.name(c).to_string());
+                return Err(AlmanacError::Cycle(names.collect()));
+            }
+        }
+        Ok(())
+    }
+
+    fn find_cycle(
+        &self,
+        category: Category,
+        state: &mut [u8],
+        path: &mut Vec<Category>,
+    ) -> Option<Vec<Category>> {
+        match state[category.0] {
+            1 => {
+                let start = path.iter().position(|&c| c == category).unwrap();
+                return Some([&path[start..], &[category]].concat());
+            }
+            2 => return None,
+            _ => {}
+        }
+        state[category.0] = 1;
+        path.push(category);
+        for map in self.mappings.iter().filter(|map| map.form == category) {
+            if let Some(cycle) = self.find_cycle(map.to, state, path) {
+                return Some(cycle);
+            }
+        }
+        path.pop();
+        state[category.0] = 2;
+        None
+    }
+
+    fn count_paths(
+        &self,
+        from: Category,
+        to: Category,
+        memo: &mut HashMap<Category, usize>,
+    ) -> usize {
+        if from == to {
+            return 1;
+        }
+        if let Some(&count) = memo.get(&from) {
+            return count;
+        }
+        let count = self
             .mappings
             .iter()
-            .find(|map| map.form == step)
-            .unwrap_or_else(|| panic!("Can't find mapping for {:?}", step));
-        numbers = numbers.into_iter().map(|n| map.map(n)).collect::<Vec<_>>();
-        step = map.to.clone();
+            .filter(|map| map.form == from)
+            .map(|map| self.count_paths(map.to, to, memo))
+            .sum();
+        memo.insert(from, count);
+        count
+    }
+
+    fn path_between(&self, from: &str, to: &str) -> Result<Vec<&Mapping>, AlmanacError> {
+        let (from_category, to_category) = (self.category(from)?, self.category(to)?);
+        self.check_cycles()?;
+        let mut memo = HashMap::new();
+        match self.count_paths(from_category, to_category, &mut memo) {
+            0 => return Err(AlmanacError::NoPath(from.to_string(), to.to_string())),
+            1 => {}
+            _ => {
+                return Err(AlmanacError::AmbiguousPath(
+                    from.to_string(),
+                    to.to_string(),
+                ))
+            }
+        }
+        let mut step = from_category;
+        let mut path = Vec::new();
+        while step != to_category {
+            let map = self
+                .mappings
+                .iter()
+                .find(|map| {
+                    map.form == step && self.count_paths(map.to, to_category, &mut memo) > 0
+                })
+                .unwrap();
+            path.push(map);
+            step = map.to;
+        }
+        Ok(path)
+    }
+
+    fn mapping_between(&self, from: &str, to: &str) -> Result<Mapping, AlmanacError> {
+        let from_category = self.category(from)?;
+        let identity = Mapping::new(from_category, from_category, &[]);
+        Ok(self
+            .path_between(from, to)?
+            .into_iter()
+            .fold(identity, |composed, map| composed.then(map)))
+    }
+
+    // Walks the stages backwards and returns the ranges for every category,
+    // starting with `to` and ending with `from`.
+    fn reverse_lookup(
+        &self,
+        from: &str,
+        to: &str,
+        ranges: RangeSet<u64>,
+    ) -> Result<Vec<(Category, RangeSet<u64>)>, AlmanacError> {
+        let mut stages = vec![(self.category(to)?, ranges)];
+        for map in self.path_between(from, to)?.into_iter().rev() {
+            let preimage = map.preimage(&stages.last().unwrap().1);
+            stages.push((map.form, preimage));
+        }
+        Ok(stages)
     }
-    numbers.into_iter().min().unwrap()
+}
+
+fn seed_to_location(input: &Plan) -> Mapping {
+    input
+        .mapping_between("seed", "location")
+        .unwrap_or_else(|err| panic!("Can't map seeds to locations: {:?}", err))
+}
+
+fn first_part(input: &Plan) -> u64 {
+    let map = seed_to_location(input);
+    input.seeds.iter().map(|&n| map.map(n)).min().unwrap()
+}
+
+fn seed_ranges(input: &Plan) -> RangeSet<u64> {
+    RangeSet::from_ranges(input.seeds.chunks(2).map(|w| (w[0], w[0] + w[1])))
 }
 
 fn second_part(input: &Plan) -> u64 {
-    let mut step = &PlantStep::Seed;
-    let mut ordered_mappings = Vec::new();
-    while step != &PlantStep::Location {
-        let map = input
-            .mappings
-            .iter()
-            .find(|map| map.form == *step)
-            .expect("Can't find mapping");
-        step = &map.to;
-        ordered_mappings.push(&map.intervals);
-    }
-    let seed_intervals = input
-        .seeds
-        .chunks(2)
-        .map(|w| (w[0], w[0] + w[1]))
-        .collect::<Vec<_>>();
-    let mapped_intervals =
-        ordered_mappings
-            .into_iter()
-            .fold(seed_intervals, |intervals, mappings| {
-                intervals
-                    .iter()
-                    .flat_map(|&(start, end)| {
-                        let mut mapped = Vec::new();
-                        let mut unmapped = vec![(start, end)];
-                        for map_interval in mappings {
-                            let mut interval_mapped = Vec::new();
-                            for (start, end) in unmapped {
-                                let left = (start, end.min(map_interval.from));
-                                let center = (
-                                    start.max(map_interval.from),
-                                    (map_interval.from + map_interval.len).min(end),
-                                );
-                                let right =
-                                    ((map_interval.from + map_interval.len).max(start), end);
-                                if left.0 < left.1 {
-                                    interval_mapped.push(left);
-                                }
-                                if center.0 < center.1 {
-                                    mapped.push((
-                                        center.0.checked_add_signed(map_interval.shift).unwrap(),
-                                        center.1.checked_add_signed(map_interval.shift).unwrap(),
-                                    ));
-                                }
-                                if right.0 < right.1 {
-                                    interval_mapped.push(right);
-                                }
-                            }
-                            unmapped = interval_mapped;
-                        }
-                        mapped.extend(unmapped);
-                        mapped
-                    })
-                    .collect()
-            });
-    mapped_intervals.iter().map(|i| i.0).min().unwrap()
+    let map = seed_to_location(input);
+    map.map_ranges(&seed_ranges(input))
+        .min()
+        .expect("No seed ranges given")
+}
+
+// Walks the locations upwards from 0 and stops at the first one reachable from the seeds.
+fn lowest_location(input: &Plan, seeds: &RangeSet<u64>) -> Option<u64> {
+    let mut pieces = seed_to_location(input).intervals;
+    pieces.sort_unstable_by_key(|interval| interval.image().0);
+    let mut lowest: Option<u64> = None;
+    for interval in pieces {
+        if lowest.is_some_and(|lowest| lowest <= interval.image().0) {
+            break;
+        }
+        let domain = RangeSet::from_ranges([(interval.from, interval.to_exclusive)]);
+        if let Some(seed) = domain.intersection(seeds).min() {
+            let location = seed.checked_add_signed(interval.shift).unwrap();
+            lowest = Some(lowest.map_or(location, |lowest| lowest.min(location)));
+        }
+    }
+    lowest
+}
+
+fn print_reverse_lookup(input: &Plan, start: u64, len: u64) {
+    let locations = RangeSet::from_ranges([(start, start + len)]);
+    let stages = input
+        .reverse_lookup("seed", "location", locations)
+        .unwrap_or_else(|err| panic!("Can't map locations to seeds: {:?}", err));
+    for (category, ranges) in stages {
+        println!("{}: {:?}", input.categories.name(category), ranges.ranges);
+    }
 }
 
 fn main() {
@@ -199,6 +555,24 @@ fn main() {
     println!("First part: {}", first_part);
     let second_part = second_part(&plan);
     println!("Second part: {}", second_part);
+    let backwards = lowest_location(&plan, &seed_ranges(&plan)).expect("No seed ranges given");
+    println!("Second part (walking backwards): {}", backwards);
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if let [command, start, rest @ ..] = args.as_slice() {
+        if command == "reverse" {
+            let start = start.parse::<u64>().expect("Location is not a number");
+            let len = rest
+                .first()
+                .map_or(1, |len| len.parse().expect("Length is not a number"));
+            print_reverse_lookup(&plan, start, len);
+        }
+    }
+    if let Some(location_to_seed) = seed_to_location(&plan).invert() {
+        println!(
+            "Seed for second part: {}",
+            location_to_seed.map(second_part)
+        );
+    }
 }
 
 #[cfg(test)]
@@ -237,4 +611,230 @@ mod tests {
         assert_ne!(result, 35081694);
         assert_eq!(result, 9622622);
     }
+
+    #[test]
+    fn test_mapping_normalized() {
+        let data = include_str!("../inputs/test.txt");
+        let plan = parse_plan(data);
+        let map = &plan.mappings[0];
+        assert_eq!(map.intervals.len(), 4);
+        assert_eq!(map.intervals[0].from, 0);
+        assert_eq!(map.intervals[3].to_exclusive, u64::MAX);
+        assert!(map
+            .intervals
+            .windows(2)
+            .all(|w| w[0].to_exclusive == w[1].from));
+        assert_eq!([79, 14, 55, 13].map(|n| map.map(n)), [81, 14, 57, 13]);
+    }
+
+    #[test]
+    fn test_mapping_composition() {
+        let data = include_str!("../inputs/test.txt");
+        let plan = parse_plan(data);
+        let composed = seed_to_location(&plan);
+        assert_eq!(plan.categories.name(composed.to), "location");
+        for seed in 0..200 {
+            let stepwise = plan.mappings.iter().fold(seed, |n, map| map.map(n));
+            assert_eq!(composed.map(seed), stepwise);
+        }
+    }
+
+    #[test]
+    fn test_mapping_inversion() {
+        let data = include_str!("../inputs/test.txt");
+        let plan = parse_plan(data);
+        let composed = seed_to_location(&plan);
+        let inverse = composed.invert().unwrap();
+        assert_eq!(plan.categories.name(inverse.form), "location");
+        for seed in 0..200 {
+            assert_eq!(inverse.map(composed.map(seed)), seed);
+        }
+        let identity = Mapping::new(composed.form, composed.form, &[]);
+        assert_eq!(composed.then(&inverse).intervals, identity.intervals);
+        let collapsing = Interval {
+            from: 10,
+            to_exclusive: 20,
+            shift: 5,
+        };
+        let map = Mapping::new(Category(0), Category(1), &[collapsing]);
+        assert!(map.invert().is_none());
+    }
+
+    #[test]
+    fn test_seed_ranges_stay_coalesced() {
+        let data = include_str!("../inputs/test.txt");
+        let plan = parse_plan(data);
+        let mut ranges = seed_ranges(&plan);
+        for map in &plan.mappings {
+            ranges = map.map_ranges(&ranges);
+            assert!(ranges.ranges.windows(2).all(|w| w[0].1 < w[1].0));
+        }
+        assert_eq!(
+            ranges,
+            seed_to_location(&plan).map_ranges(&seed_ranges(&plan))
+        );
+        assert_eq!(ranges.min(), Some(46));
+    }
+
+    #[test]
+    fn test_range_set_operations() {
+        let a = RangeSet::from_ranges([(5, 10), (0, 3), (2, 4), (10, 12)]);
+        assert_eq!(a.ranges, vec![(0, 4), (5, 12)]);
+        let b = RangeSet::from_ranges([(3, 6), (8, 9), (11, 20)]);
+        assert_eq!(a.union(&b).ranges, vec![(0, 20)]);
+        assert_eq!(
+            a.intersection(&b).ranges,
+            vec![(3, 4), (5, 6), (8, 9), (11, 12)]
+        );
+        assert_eq!(a.difference(&b).ranges, vec![(0, 3), (6, 8), (9, 11)]);
+        assert!(a.difference(&a).is_empty());
+        assert!(a.contains(0) && !a.contains(4) && a.contains(11) && !a.contains(12));
+        assert_eq!(RangeSet::<u64>::new().min(), None);
+    }
+
+    fn random_range_set(state: &mut u64, limit: u64) -> RangeSet<u64> {
+        let mut next = || {
+            *state ^= *state << 13;
+            *state ^= *state >> 7;
+            *state ^= *state << 17;
+            *state % limit
+        };
+        let count = next() % 6;
+        RangeSet::from_ranges((0..count).map(|_| {
+            let start = next();
+            (start, start + next() % 16)
+        }))
+    }
+
+    #[test]
+    fn test_range_set_against_bitset() {
+        const LIMIT: u64 = 64;
+        let to_bits = |set: &RangeSet<u64>| (0..LIMIT * 2).map(|n| set.contains(n)).collect();
+        let mut state = 0x2023_1205;
+        for _ in 0..500 {
+            let a = random_range_set(&mut state, LIMIT);
+            let b = random_range_set(&mut state, LIMIT);
+            let (bits_a, bits_b): (Vec<bool>, Vec<bool>) = (to_bits(&a), to_bits(&b));
+            let expected = |op: fn(bool, bool) -> bool| {
+                bits_a
+                    .iter()
+                    .zip(&bits_b)
+                    .map(|(&x, &y)| op(x, y))
+                    .collect::<Vec<_>>()
+            };
+            let sets = [a.union(&b), a.intersection(&b), a.difference(&b)];
+            assert_eq!(to_bits(&sets[0]), expected(|x, y| x || y));
+            assert_eq!(to_bits(&sets[1]), expected(|x, y| x && y));
+            assert_eq!(to_bits(&sets[2]), expected(|x, y| x && !y));
+            for set in sets {
+                assert!(set.ranges.windows(2).all(|w| w[0].1 < w[1].0));
+                assert!(set.iter().all(|range| range.0 < range.1));
+            }
+        }
+    }
+
+    #[test]
+    fn test_mapping_between_any_categories() {
+        let data = include_str!("../inputs/test.txt");
+        let plan = parse_plan(data);
+        let water_to_humidity = plan.mapping_between("water", "humidity").unwrap();
+        let light_to_humidity = plan.mapping_between("light", "humidity").unwrap();
+        let water_to_light = &plan.mappings[3];
+        assert_eq!(water_to_light.then(&light_to_humidity), water_to_humidity);
+        assert_eq!(
+            plan.mapping_between("soil", "seed"),
+            Err(AlmanacError::NoPath("soil".to_string(), "seed".to_string()))
+        );
+        assert_eq!(
+            plan.mapping_between("seed", "mars"),
+            Err(AlmanacError::UnknownCategory("mars".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_reordered_almanac_with_extra_stage() {
+        let data = "seeds: 1 5\n\nsoil-to-location map:\n10 0 10\n\n\
+            seed-to-soil map:\n0 1 1\n\nsoil-to-extra map:\n0 0 100";
+        let plan = parse_plan(data);
+        assert_eq!(first_part(&plan), 10);
+        assert_eq!(plan.mapping_between("seed", "extra").unwrap().map(5), 5);
+    }
+
+    #[test]
+    fn test_ambiguous_and_cyclic_almanacs() {
+        let data = "seeds: 1\n\nseed-to-soil map:\n0 1 1\n\n\
+            soil-to-location map:\n0 1 1\n\nseed-to-location map:\n5 1 1";
+        let plan = parse_plan(data);
+        assert_eq!(
+            plan.mapping_between("seed", "location"),
+            Err(AlmanacError::AmbiguousPath(
+                "seed".to_string(),
+                "location".to_string()
+            ))
+        );
+        let data = "seeds: 1\n\nseed-to-soil map:\n0 1 1\n\n\
+            soil-to-water map:\n0 1 1\n\nwater-to-soil map:\n5 1 1";
+        let plan = parse_plan(data);
+        let cycle = ["soil", "water", "soil"].map(String::from).to_vec();
+        assert_eq!(
+            plan.mapping_between("seed", "water"),
+            Err(AlmanacError::Cycle(cycle))
+        );
+    }
+
+    #[test]
+    fn test_reverse_lookup() {
+        let data = include_str!("../inputs/test.txt");
+        let plan = parse_plan(data);
+        let location = RangeSet::from_ranges([(46, 47)]);
+        let stages = plan.reverse_lookup("seed", "location", location).unwrap();
+        let values = stages
+            .iter()
+            .map(|(category, ranges)| (plan.categories.name(*category), ranges.min().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            values,
+            vec![
+                ("location", 46),
+                ("humidity", 46),
+                ("temperature", 45),
+                ("light", 77),
+                ("water", 84),
+                ("fertilizer", 84),
+                ("soil", 84),
+                ("seed", 82),
+            ]
+        );
+        let seeds = &stages.last().unwrap().1;
+        assert!(seeds.intersection(&seed_ranges(&plan)).contains(82));
+    }
+
+    #[test]
+    fn test_reverse_lookup_matches_forward() {
+        let data = include_str!("../inputs/test.txt");
+        let plan = parse_plan(data);
+        let map = seed_to_location(&plan);
+        let locations = RangeSet::from_ranges([(0, 10), (50, 60)]);
+        let stages = plan
+            .reverse_lookup("seed", "location", locations.clone())
+            .unwrap();
+        let seeds = &stages.last().unwrap().1;
+        for seed in 0..200 {
+            assert_eq!(seeds.contains(seed), locations.contains(map.map(seed)));
+        }
+        assert_eq!(map.map_ranges(seeds), locations);
+    }
+
+    #[test]
+    fn test_lowest_location() {
+        let data = include_str!("../inputs/test.txt");
+        let plan = parse_plan(data);
+        let seeds = RangeSet::from_ranges(plan.seeds.iter().map(|&seed| (seed, seed + 1)));
+        assert_eq!(lowest_location(&plan, &seeds), Some(first_part(&plan)));
+        assert_eq!(
+            lowest_location(&plan, &seed_ranges(&plan)),
+            Some(second_part(&plan))
+        );
+        assert_eq!(lowest_location(&plan, &RangeSet::new()), None);
+    }
 }
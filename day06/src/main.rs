@@ -1,74 +1,237 @@
+use std::ops::RangeInclusive;
+
 fn first_part(input: &str) -> u64 {
-    parse_races(input)
+    parse_sheet(input)
+        .races
         .iter()
         .map(|(time, distance)| calculate_win_combinations(time, distance))
         .reduce(|acc, a| acc * a)
         .expect("No races given")
 }
 
-fn calculate_win_combinations(time: &u64, distance: &u64) -> u64 {
-    let (lower, upper);
-    {
-        let (time, distance) = (*time as f64, *distance as f64);
-        let x = f64::sqrt(time * time - 4_f64 * distance);
-        (lower, upper) = (((time - x) / 2_f64).ceil(), ((time + x) / 2_f64).floor());
-    }
-    let (mut lower, mut upper) = (lower as u64, upper as u64);
-    if lower * (*time - lower) == *distance {
-        // if using lower bound match longest distance add one
+fn integer_sqrt(n: u128) -> u128 {
+    if n < 2 {
+        return n;
+    }
+    let mut x = n;
+    let mut y = x.div_ceil(2);
+    while y < x {
+        x = y;
+        y = (x + n / x) / 2;
+    }
+    x
+}
+
+fn standard_winning_holds(time: u64, distance: u64) -> Option<RangeInclusive<u64>> {
+    let (time, distance) = (time as u128, distance as u128);
+    let travelled = |hold: u128| hold * (time - hold);
+    let discriminant = match (time * time).checked_sub(4 * distance) {
+        Some(discriminant) if discriminant > 0 => discriminant,
+        _ => return None,
+    };
+    // integer root is floor of the real one, so the bound is off by one at most
+    let mut lower = (time - integer_sqrt(discriminant)) / 2;
+    while lower <= time / 2 && travelled(lower) <= distance {
         lower += 1;
     }
-    if upper * (*time - upper) == *distance {
-        // if using upper bound match longest distance sub one
-        upper -= 1;
+    while lower > 0 && travelled(lower - 1) > distance {
+        lower -= 1;
+    }
+    if lower > time / 2 {
+        return None;
+    }
+    // distance is symmetric around time / 2
+    let upper = time - lower;
+    Some(lower as u64..=upper as u64)
+}
+
+fn count_holds(holds: Option<RangeInclusive<u64>>) -> u64 {
+    holds.map_or(0, |holds| holds.end() - holds.start() + 1)
+}
+
+fn calculate_win_combinations(time: &u64, distance: &u64) -> u64 {
+    count_holds(standard_winning_holds(*time, *distance))
+}
+
+// first value in [low, high) where predicate holds, high if there is none
+fn first_true(mut low: u64, mut high: u64, predicate: impl Fn(u64) -> bool) -> u64 {
+    while low < high {
+        let mid = low + (high - low) / 2;
+        if predicate(mid) {
+            high = mid;
+        } else {
+            low = mid + 1;
+        }
+    }
+    low
+}
+
+trait RaceModel {
+    fn distance(&self, time: u64, hold: u64) -> u128;
+
+    // Fallback for models without closed form. Distance has to be non-decreasing
+    // up to its peak and strictly decreasing after it.
+    fn winning_holds(&self, time: u64, record: u64) -> Option<RangeInclusive<u64>> {
+        let record = record as u128;
+        let distance = |hold| self.distance(time, hold);
+        let peak = first_true(0, time, |hold| distance(hold) > distance(hold + 1));
+        if distance(peak) <= record {
+            return None;
+        }
+        let first = first_true(0, peak, |hold| distance(hold) > record);
+        let last = first_true(peak, time + 1, |hold| distance(hold) <= record) - 1;
+        Some(first..=last)
+    }
+}
+
+struct Standard;
+
+impl RaceModel for Standard {
+    fn distance(&self, time: u64, hold: u64) -> u128 {
+        hold as u128 * (time - hold) as u128
+    }
+
+    fn winning_holds(&self, time: u64, record: u64) -> Option<RangeInclusive<u64>> {
+        standard_winning_holds(time, record)
+    }
+}
+
+struct Acceleration {
+    multiplier: u64,
+}
+
+impl RaceModel for Acceleration {
+    fn distance(&self, time: u64, hold: u64) -> u128 {
+        self.multiplier as u128 * Standard.distance(time, hold)
+    }
+
+    fn winning_holds(&self, time: u64, record: u64) -> Option<RangeInclusive<u64>> {
+        if self.multiplier == 0 {
+            return None;
+        }
+        standard_winning_holds(time, record / self.multiplier)
+    }
+}
+
+struct SpeedCap {
+    cap: u64,
+}
+
+impl RaceModel for SpeedCap {
+    fn distance(&self, time: u64, hold: u64) -> u128 {
+        hold.min(self.cap) as u128 * (time - hold) as u128
+    }
+}
+
+struct Friction {
+    penalty: u64,
+}
+
+impl RaceModel for Friction {
+    fn distance(&self, time: u64, hold: u64) -> u128 {
+        hold.saturating_sub(self.penalty) as u128 * (time - hold) as u128
+    }
+
+    fn winning_holds(&self, time: u64, record: u64) -> Option<RangeInclusive<u64>> {
+        let holds = standard_winning_holds(time.checked_sub(self.penalty)?, record)?;
+        Some(holds.start() + self.penalty..=holds.end() + self.penalty)
+    }
+}
+
+// model format: standard, acceleration:<multiplier>, cap:<speed>, friction:<penalty>
+impl From<&str> for Box<dyn RaceModel> {
+    fn from(value: &str) -> Self {
+        let (name, parameter) = value.split_once(':').unwrap_or((value, "0"));
+        let parameter = parameter
+            .parse::<u64>()
+            .expect("Model parameter is not a number");
+        match name {
+            "standard" => Box::new(Standard),
+            "acceleration" => Box::new(Acceleration {
+                multiplier: parameter,
+            }),
+            "cap" => Box::new(SpeedCap { cap: parameter }),
+            "friction" => Box::new(Friction { penalty: parameter }),
+            _ => panic!("Unknown race model {}", value),
+        }
     }
-    (upper + 1).saturating_sub(lower)
 }
 
 fn second_part(input: &str) -> u64 {
-    let (time, distance) = parse_races_second(input);
+    let (time, distance) = parse_sheet(input)
+        .race
+        .unwrap_or_else(|err| panic!("Can't read the single race: {:?}", err));
     calculate_win_combinations(&time, &distance)
 }
 
-fn parse_races(input: &str) -> Vec<(u64, u64)> {
-    let mut lines = input.lines();
-    if let (Some(first_line), Some(second_line)) = (lines.next(), lines.next()) {
-        return first_line
-            .split_ascii_whitespace()
-            .skip(1)
-            .zip(second_line.split_ascii_whitespace().skip(1))
-            .map(|(t, d)| {
-                (
-                    t.parse::<u64>().expect("Time too large"),
-                    d.parse::<u64>().expect("Distance too large"),
-                )
-            })
-            .collect();
-    }
-    panic!("Can't parse races")
-}
-
-fn parse_races_second(input: &str) -> (u64, u64) {
-    let mut lines = input.lines();
-    if let (Some(first_line), Some(second_line)) = (lines.next(), lines.next()) {
-        return (
-            first_line
-                .replace(' ', "")
-                .split_once(':')
-                .expect("Wrong format of first line")
-                .1
-                .parse()
-                .expect("Time is too large"),
-            second_line
-                .replace(' ', "")
-                .split_once(':')
-                .expect("Wrong format of second line")
-                .1
-                .parse()
-                .expect("Distance is too large"),
-        );
+#[derive(Debug, PartialEq)]
+enum RaceSheetError {
+    MissingLine(&'static str),
+    InvalidNumber(String),
+    Overflow(String),
+    MismatchedColumns { times: usize, distances: usize },
+}
+
+#[derive(Debug, PartialEq)]
+struct RaceSheet {
+    races: Vec<(u64, u64)>,
+    // the columns read as one race, may overflow even when every column fits
+    race: Result<(u64, u64), RaceSheetError>,
+}
+
+// Numbers may carry a unit suffix ("7ms") or be followed by a unit ("7 ms").
+fn parse_columns(values: &str) -> Result<Vec<&str>, RaceSheetError> {
+    values
+        .split_ascii_whitespace()
+        .map(|token| token.trim_end_matches(|c: char| c.is_ascii_alphabetic()))
+        .filter(|number| !number.is_empty())
+        .map(|number| {
+            if number.bytes().all(|b| b.is_ascii_digit()) {
+                Ok(number)
+            } else {
+                Err(RaceSheetError::InvalidNumber(number.to_string()))
+            }
+        })
+        .collect()
+}
+
+// Columns are already checked to be digits only, so parsing can only overflow.
+fn parse_number(number: &str) -> Result<u64, RaceSheetError> {
+    number
+        .parse()
+        .map_err(|_| RaceSheetError::Overflow(number.to_string()))
+}
+
+fn find_line<'a>(input: &'a str, label: &'static str) -> Result<&'a str, RaceSheetError> {
+    input
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().to_ascii_lowercase().starts_with(label))
+        .map(|(_, values)| values)
+        .ok_or(RaceSheetError::MissingLine(label))
+}
+
+fn parse_race_sheet(input: &str) -> Result<RaceSheet, RaceSheetError> {
+    let times = parse_columns(find_line(input, "time")?)?;
+    let distances = parse_columns(find_line(input, "distance")?)?;
+    if times.len() != distances.len() {
+        return Err(RaceSheetError::MismatchedColumns {
+            times: times.len(),
+            distances: distances.len(),
+        });
     }
-    panic!("Can't parse races")
+    let races = times
+        .iter()
+        .zip(&distances)
+        .map(|(time, distance)| Ok((parse_number(time)?, parse_number(distance)?)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let race = parse_number(&times.concat())
+        .and_then(|time| Ok((time, parse_number(&distances.concat())?)));
+    Ok(RaceSheet { races, race })
+}
+
+fn parse_sheet(input: &str) -> RaceSheet {
+    parse_race_sheet(input).unwrap_or_else(|err| panic!("Can't parse races: {:?}", err))
 }
 
 fn main() {
@@ -77,6 +240,15 @@ fn main() {
     println!("First part: {}", first_part);
     let second_part = second_part(input);
     println!("Second part: {}", second_part);
+    for model in std::env::args().skip(1) {
+        let race_model: Box<dyn RaceModel> = model.as_str().into();
+        let holds = parse_sheet(input)
+            .races
+            .into_iter()
+            .map(|(time, distance)| race_model.winning_holds(time, distance))
+            .collect::<Vec<_>>();
+        println!("Model {}: {:?}", model, holds);
+    }
 }
 
 #[cfg(test)]
@@ -110,4 +282,140 @@ mod tests {
         let result = second_part(data);
         assert_eq!(result, 34788142);
     }
+
+    #[test]
+    fn test_integer_sqrt() {
+        for n in 0..10_000u128 {
+            let root = integer_sqrt(n);
+            assert!(root * root <= n && (root + 1) * (root + 1) > n);
+        }
+        assert_eq!(integer_sqrt(u128::MAX), u64::MAX as u128);
+    }
+
+    #[test]
+    fn test_win_combinations_against_brute_force() {
+        let mut state = 0x2023_1206u64;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        for _ in 0..2_000 {
+            let time = next() % 200;
+            let distance = next() % (time * time / 4 + 10);
+            let expected = (0..=time)
+                .filter(|hold| hold * (time - hold) > distance)
+                .count() as u64;
+            assert_eq!(calculate_win_combinations(&time, &distance), expected);
+        }
+    }
+
+    #[test]
+    fn test_win_combinations_large_races() {
+        assert_eq!(calculate_win_combinations(&u64::MAX, &0), u64::MAX - 1);
+        let time = (1u64 << 32) + 1;
+        let best = (time / 2) * (time - time / 2);
+        assert_eq!(calculate_win_combinations(&time, &(best - 1)), 2);
+        assert_eq!(calculate_win_combinations(&time, &best), 0);
+        let time = 1u64 << 32;
+        let distance = (1u64 << 62) - 1;
+        assert_eq!(calculate_win_combinations(&time, &distance), 1);
+    }
+
+    struct Searched<M: RaceModel>(M);
+
+    impl<M: RaceModel> RaceModel for Searched<M> {
+        fn distance(&self, time: u64, hold: u64) -> u128 {
+            self.0.distance(time, hold)
+        }
+    }
+
+    fn brute_force(model: &dyn RaceModel, time: u64, record: u64) -> Option<RangeInclusive<u64>> {
+        let mut holds = (0..=time).filter(|&hold| model.distance(time, hold) > record as u128);
+        let first = holds.next()?;
+        Some(first..=holds.last().unwrap_or(first))
+    }
+
+    #[test]
+    fn test_race_models_against_brute_force() {
+        let models: Vec<Box<dyn RaceModel>> = vec![
+            Box::new(Standard),
+            Box::new(Acceleration { multiplier: 3 }),
+            Box::new(SpeedCap { cap: 4 }),
+            Box::new(Friction { penalty: 2 }),
+            Box::new(Searched(Standard)),
+            Box::new(Searched(Acceleration { multiplier: 3 })),
+            Box::new(Searched(Friction { penalty: 2 })),
+        ];
+        for model in &models {
+            for time in 0..40 {
+                for record in (0..200).step_by(7) {
+                    let expected = brute_force(model.as_ref(), time, record);
+                    assert_eq!(model.winning_holds(time, record), expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_race_model_intervals() {
+        assert_eq!(Standard.winning_holds(7, 9), Some(2..=5));
+        assert_eq!(Standard.winning_holds(30, 200), Some(11..=19));
+        assert_eq!(count_holds(Standard.winning_holds(30, 200)), 9);
+        let model: Box<dyn RaceModel> = "cap:3".into();
+        assert_eq!(model.winning_holds(7, 9), Some(2..=3));
+        let model: Box<dyn RaceModel> = "friction:1".into();
+        assert_eq!(model.winning_holds(7, 9), None);
+        let model: Box<dyn RaceModel> = "acceleration:2".into();
+        assert_eq!(model.winning_holds(7, 9), Some(1..=6));
+    }
+
+    #[test]
+    fn test_race_sheet() {
+        let data = include_str!("../inputs/test.txt");
+        let sheet = parse_race_sheet(data).unwrap();
+        assert_eq!(sheet.races, vec![(7, 9), (15, 40), (30, 200)]);
+        assert_eq!(sheet.race, Ok((71530, 940200)));
+        let data = "Race sheet\n\nTime (ms):  7ms  15 ms 30\nDistance:  9mm 40mm 200 mm\n";
+        assert_eq!(parse_race_sheet(data).unwrap(), sheet);
+    }
+
+    #[test]
+    fn test_race_sheet_errors() {
+        assert_eq!(
+            parse_race_sheet("Time: 7 15\nDistance: 9"),
+            Err(RaceSheetError::MismatchedColumns {
+                times: 2,
+                distances: 1
+            })
+        );
+        assert_eq!(
+            parse_race_sheet("Time: 7 15"),
+            Err(RaceSheetError::MissingLine("distance"))
+        );
+        assert_eq!(
+            parse_race_sheet("Time: 7 1x5\nDistance: 9 40"),
+            Err(RaceSheetError::InvalidNumber("1x5".to_string()))
+        );
+        let too_large = "Time: 99999999999999999999 1\nDistance: 1 1";
+        assert_eq!(
+            parse_race_sheet(too_large),
+            Err(RaceSheetError::Overflow("99999999999999999999".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_race_sheet_single_race_overflow() {
+        let data = "Time: 71 71 71 71 71 71\nDistance: 1000 1000 1000 1000 1000 1000";
+        let sheet = parse_race_sheet(data).unwrap();
+        assert_eq!(sheet.races, vec![(71, 1000); 6]);
+        assert_eq!(
+            sheet.race,
+            Err(RaceSheetError::Overflow(
+                "100010001000100010001000".to_string()
+            ))
+        );
+        assert_eq!(first_part(data), 32u64.pow(6));
+    }
 }
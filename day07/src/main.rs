@@ -1,10 +1,248 @@
-const HIGH_CARD: u32 = 1;
-const ONE_PAIR: u32 = 2;
-const TWO_PAIR: u32 = 3;
-const THREE_OF_A_KIND: u32 = 4;
-const FULL_HOUSE: u32 = 5;
-const FOUR_OF_A_KIND: u32 = 6;
-const FIVE_OF_A_KIND: u32 = 7;
+use std::{cmp::Ordering, fmt::Display};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+enum HandType {
+    HighCard,
+    OnePair,
+    TwoPair,
+    ThreeOfAKind,
+    Straight,
+    FullHouse,
+    FourOfAKind,
+    FiveOfAKind,
+}
+
+impl HandType {
+    // Group sizes have to be sorted from the largest. Longer hands are
+    // classified by their two largest groups.
+    fn from_groups(groups: &[u8]) -> HandType {
+        match groups {
+            [largest, ..] if *largest >= 5 => HandType::FiveOfAKind,
+            [4, ..] => HandType::FourOfAKind,
+            [3, second, ..] if *second >= 2 => HandType::FullHouse,
+            [3, ..] => HandType::ThreeOfAKind,
+            [2, 2, ..] => HandType::TwoPair,
+            [2, ..] => HandType::OnePair,
+            _ => HandType::HighCard,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+struct Card(u32);
+
+// Fields are compared in order, so the hand type goes first and cards break ties.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug)]
+struct Hand {
+    hand_type: HandType,
+    cards: Vec<Card>,
+}
+
+trait RuleSet {
+    fn card(&self, card: char) -> Card;
+
+    fn is_wild(&self, _card: char) -> bool {
+        false
+    }
+
+    fn hand_size(&self) -> Option<usize> {
+        None
+    }
+
+    // Wildcards always join the largest group, that gives the best hand.
+    fn groups(&self, cards: &str) -> Vec<u8> {
+        let mut counts: Vec<(char, u8)> = Vec::new();
+        let mut wildcards = 0;
+        for card in cards.chars() {
+            if self.is_wild(card) {
+                wildcards += 1;
+            } else if let Some((_, count)) = counts.iter_mut().find(|(c, _)| *c == card) {
+                *count += 1;
+            } else {
+                counts.push((card, 1));
+            }
+        }
+        let mut groups = counts
+            .into_iter()
+            .map(|(_, count)| count)
+            .collect::<Vec<_>>();
+        groups.sort_unstable_by(|a, b| b.cmp(a));
+        match groups.first_mut() {
+            Some(largest) => *largest += wildcards,
+            None => groups.push(wildcards),
+        }
+        groups
+    }
+
+    fn hand_type(&self, cards: &str) -> HandType {
+        HandType::from_groups(&self.groups(cards))
+    }
+
+    fn promote(&self, cards: &str) -> String {
+        self.promote_to_largest_group(cards)
+    }
+
+    // Replaces wildcards by the most common card, the stronger one on ties.
+    fn promote_to_largest_group(&self, cards: &str) -> String {
+        let best = cards
+            .chars()
+            .filter(|&card| !self.is_wild(card))
+            .max_by_key(|&card| (cards.matches(card).count(), self.card(card)));
+        match best {
+            Some(best) => cards
+                .chars()
+                .map(|card| if self.is_wild(card) { best } else { card })
+                .collect(),
+            None => cards.to_string(),
+        }
+    }
+
+    fn hand(&self, cards: &str) -> Hand {
+        if let Some(size) = self.hand_size() {
+            if cards.chars().count() != size {
+                panic!("Hand {} doesn't have {} cards", cards, size);
+            }
+        }
+        Hand {
+            hand_type: self.hand_type(cards),
+            cards: cards.chars().map(|card| self.card(card)).collect(),
+        }
+    }
+}
+
+struct Standard;
+
+impl RuleSet for Standard {
+    fn card(&self, card: char) -> Card {
+        Card(get_card_priority(&card))
+    }
+}
+
+struct JokersWild;
+
+impl RuleSet for JokersWild {
+    fn card(&self, card: char) -> Card {
+        match card {
+            'J' => Card(1),
+            card => Card(get_card_priority(&card)),
+        }
+    }
+
+    fn is_wild(&self, card: char) -> bool {
+        card == 'J'
+    }
+}
+
+struct Rules {
+    ranks: Vec<char>,
+    wildcards: Vec<char>,
+    hand_size: usize,
+    straights: bool,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules {
+            ranks: "23456789TJQKA".chars().collect(),
+            wildcards: Vec::new(),
+            hand_size: 5,
+            straights: false,
+        }
+    }
+}
+
+impl Rules {
+    // option format: ranks=<weakest..strongest>, wild=<cards>, size=<n>, straights
+    fn with_option(mut self, option: &str) -> Rules {
+        match option.split_once('=') {
+            Some(("ranks", ranks)) => self.ranks = ranks.chars().collect(),
+            Some(("wild", wildcards)) => self.wildcards = wildcards.chars().collect(),
+            Some(("size", size)) => self.hand_size = size.parse().expect("Size is not a number"),
+            None if option == "straights" => self.straights = true,
+            _ => panic!("Unknown rule option {}", option),
+        }
+        self
+    }
+
+    fn rank(&self, card: char) -> usize {
+        self.ranks
+            .iter()
+            .position(|&c| c == card)
+            .unwrap_or_else(|| panic!("Unknown card {}", card))
+    }
+
+    // Distinct cards within a window of consecutive ranks, wildcards fill the gaps.
+    fn is_straight(&self, cards: &str) -> bool {
+        let mut ranks = cards
+            .chars()
+            .filter(|&card| !self.is_wild(card))
+            .map(|card| self.rank(card))
+            .collect::<Vec<_>>();
+        ranks.sort_unstable();
+        let distinct = ranks.windows(2).all(|w| w[0] != w[1]);
+        let span = ranks
+            .last()
+            .zip(ranks.first())
+            .map_or(0, |(max, min)| max - min);
+        let size = cards.chars().count();
+        distinct && span < size && size <= self.ranks.len()
+    }
+}
+
+impl RuleSet for Rules {
+    fn card(&self, card: char) -> Card {
+        if self.is_wild(card) {
+            return Card(0);
+        }
+        Card(self.rank(card) as u32 + 1)
+    }
+
+    fn is_wild(&self, card: char) -> bool {
+        self.wildcards.contains(&card)
+    }
+
+    fn hand_size(&self) -> Option<usize> {
+        Some(self.hand_size)
+    }
+
+    fn promote(&self, cards: &str) -> String {
+        if self.hand_type(cards) != HandType::Straight {
+            return self.promote_to_largest_group(cards);
+        }
+        let present = cards
+            .chars()
+            .filter(|&card| !self.is_wild(card))
+            .map(|card| self.rank(card))
+            .collect::<Vec<_>>();
+        let size = cards.chars().count();
+        let highest_start = self.ranks.len() - size;
+        let start = present
+            .iter()
+            .min()
+            .map_or(highest_start, |&min| min.min(highest_start));
+        let mut missing = (start..start + size)
+            .filter(|rank| !present.contains(rank))
+            .map(|rank| self.ranks[rank]);
+        cards
+            .chars()
+            .map(|card| {
+                if self.is_wild(card) {
+                    missing.next().unwrap()
+                } else {
+                    card
+                }
+            })
+            .collect()
+    }
+
+    fn hand_type(&self, cards: &str) -> HandType {
+        let hand_type = HandType::from_groups(&self.groups(cards));
+        if self.straights && hand_type < HandType::Straight && self.is_straight(cards) {
+            return HandType::Straight;
+        }
+        hand_type
+    }
+}
 
 fn get_card_priority(card: &char) -> u32 {
     if let Some(n) = card.to_digit(10) {
@@ -20,117 +258,149 @@ fn get_card_priority(card: &char) -> u32 {
     }
 }
 
-fn get_numeric_representation_of_cards(cards: &str, joker: bool) -> u64 {
-    cards
-        .chars()
-        .fold(vec!['1'], |mut acc, c| {
-            if c.is_ascii_digit() {
-                acc.push('0');
-                acc.push(c);
-            } else if joker && c == 'J' {
-                acc.push('0');
-                acc.push('1');
-            } else {
-                acc.push('1');
-                match c {
-                    'T' => acc.push('0'),
-                    'J' => acc.push('1'),
-                    'Q' => acc.push('2'),
-                    'K' => acc.push('3'),
-                    'A' => acc.push('4'),
-                    _ => panic!("Unknown card"),
-                }
+struct HandReport<'a> {
+    cards: &'a str,
+    promoted: String,
+    hand: Hand,
+    bet: u64,
+    rank: u64,
+    winnings: u64,
+}
+
+#[derive(Debug, PartialEq)]
+enum Explanation {
+    Tie,
+    HandType {
+        stronger: HandType,
+        weaker: HandType,
+    },
+    Card {
+        position: usize,
+        stronger: char,
+        weaker: char,
+    },
+}
+
+impl Display for Explanation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Explanation::Tie => write!(f, "hands are equal"),
+            Explanation::HandType { stronger, weaker } => {
+                write!(f, "{:?} beats {:?}", stronger, weaker)
             }
-            acc
-        })
-        .into_iter()
-        .collect::<String>()
-        .parse()
-        .expect("Wrong hand cards")
-}
-
-fn get_combination_priority(mut counts: [u8; 15], joker: bool) -> u32 {
-    let mut hand_priority = HIGH_CARD;
-    let joker_index = get_card_priority(&'J') as usize;
-    let jokers = counts[joker_index];
-    if joker {
-        counts[joker_index] = 0
-    }
-    for c in counts.into_iter() {
-        match (c, hand_priority) {
-            (2, ONE_PAIR) => hand_priority = TWO_PAIR,
-            (2, THREE_OF_A_KIND) => hand_priority = FULL_HOUSE,
-            (2, _) => hand_priority = ONE_PAIR,
-            (3, ONE_PAIR) => hand_priority = FULL_HOUSE,
-            (3, _) => hand_priority = THREE_OF_A_KIND,
-            (4, _) => hand_priority = FOUR_OF_A_KIND,
-            (5, _) => hand_priority = FIVE_OF_A_KIND,
-            _ => {}
-        }
-    }
-    if joker {
-        match (jokers, hand_priority) {
-            (4 | 5, _) => hand_priority = FIVE_OF_A_KIND,
-            (3, ONE_PAIR) => hand_priority = FIVE_OF_A_KIND,
-            (3, _) => hand_priority = FOUR_OF_A_KIND,
-            (2, THREE_OF_A_KIND) => hand_priority = FIVE_OF_A_KIND,
-            (2, ONE_PAIR) => hand_priority = FOUR_OF_A_KIND,
-            (2, _) => hand_priority = THREE_OF_A_KIND,
-            (1, FOUR_OF_A_KIND) => hand_priority = FIVE_OF_A_KIND,
-            (1, THREE_OF_A_KIND) => hand_priority = FOUR_OF_A_KIND,
-            (1, TWO_PAIR) => hand_priority = FULL_HOUSE,
-            (1, ONE_PAIR) => hand_priority = THREE_OF_A_KIND,
-            (1, _) => hand_priority = ONE_PAIR,
-            _ => {}
-        };
+            Explanation::Card {
+                position,
+                stronger,
+                weaker,
+            } => write!(
+                f,
+                "card {} decides, {} beats {}",
+                position + 1,
+                stronger,
+                weaker
+            ),
+        }
     }
-    hand_priority
 }
 
-fn get_combination(cards: &str, joker: bool) -> (u32, u64) {
-    let mut card_priority_count = [0u8; 15];
-    cards
+fn explain(rules: &dyn RuleSet, first: &str, second: &str) -> (Ordering, Explanation) {
+    let (first_hand, second_hand) = (rules.hand(first), rules.hand(second));
+    let ordering = first_hand.cmp(&second_hand);
+    let (stronger, weaker) = match ordering {
+        Ordering::Less => ((second, second_hand), (first, first_hand)),
+        _ => ((first, first_hand), (second, second_hand)),
+    };
+    if stronger.1.hand_type != weaker.1.hand_type {
+        let explanation = Explanation::HandType {
+            stronger: stronger.1.hand_type,
+            weaker: weaker.1.hand_type,
+        };
+        return (ordering, explanation);
+    }
+    let differing = stronger
+        .0
         .chars()
-        .for_each(|card| card_priority_count[get_card_priority(&card) as usize] += 1);
-    (
-        get_combination_priority(card_priority_count, joker),
-        get_numeric_representation_of_cards(cards, joker),
-    )
+        .zip(weaker.0.chars())
+        .enumerate()
+        .zip(stronger.1.cards.iter().zip(&weaker.1.cards))
+        .find(|(_, (a, b))| a != b);
+    let explanation = match differing {
+        Some(((position, (stronger, weaker)), _)) => Explanation::Card {
+            position,
+            stronger,
+            weaker,
+        },
+        None => Explanation::Tie,
+    };
+    (ordering, explanation)
 }
 
-fn solve(input: &str, joker: bool) -> u64 {
+fn report<'a>(input: &'a str, rules: &dyn RuleSet) -> Vec<HandReport<'a>> {
     let mut games = input
         .lines()
         .map(|line| {
             let mut words = line.split_ascii_whitespace();
-            let (combination, priority) =
-                get_combination(words.next().expect("Unable to parse cards"), joker);
-            (
-                combination,
-                priority,
-                words
-                    .next()
-                    .expect("No bet")
-                    .parse::<u64>()
-                    .expect("Bet is not a number"),
-            )
+            let cards = words.next().expect("Unable to parse cards");
+            let bet = words
+                .next()
+                .expect("No bet")
+                .parse::<u64>()
+                .expect("Bet is not a number");
+            (cards, rules.hand(cards), bet)
         })
         .collect::<Vec<_>>();
-    games.sort_unstable_by_key(|(combo, priority, _)| (*combo, *priority));
+    games.sort_unstable_by(|(_, a, _), (_, b, _)| a.cmp(b));
     games
-        .iter()
-        .fold((1, 0), |(index, sum), (_, _, bet)| {
-            (index + 1, sum + index * bet)
+        .into_iter()
+        .zip(1..)
+        .map(|((cards, hand, bet), rank)| HandReport {
+            cards,
+            promoted: rules.promote(cards),
+            hand,
+            bet,
+            rank,
+            winnings: rank * bet,
         })
-        .1
+        .collect()
+}
+
+fn solve(input: &str, rules: &dyn RuleSet) -> u64 {
+    report(input, rules).iter().map(|game| game.winnings).sum()
+}
+
+fn print_report(input: &str, rules: &dyn RuleSet) {
+    for game in report(input, rules) {
+        println!(
+            "{:>4}. {} -> {} {:?}, bet {} wins {}",
+            game.rank, game.cards, game.promoted, game.hand.hand_type, game.bet, game.winnings
+        );
+    }
 }
 
 fn main() {
     let input = include_str!("../inputs/input.txt");
-    let first_part = solve(input, false);
+    let first_part = solve(input, &Standard);
     println!("First part: {}", first_part);
-    let second_part = solve(input, true);
+    let second_part = solve(input, &JokersWild);
     println!("Second part: {}", second_part);
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let rules_from = |options: &[String]| {
+        options
+            .iter()
+            .fold(Rules::default(), |rules, option| rules.with_option(option))
+    };
+    match args.as_slice() {
+        [] => {}
+        [command, options @ ..] if command == "report" => print_report(input, &rules_from(options)),
+        [command, first, second, options @ ..] if command == "explain" => {
+            let (ordering, explanation) = explain(&rules_from(options), first, second);
+            println!(
+                "{} is {:?} than {}: {}",
+                first, ordering, second, explanation
+            );
+        }
+        options => println!("Custom rules: {}", solve(input, &rules_from(options))),
+    }
 }
 
 #[cfg(test)]
@@ -140,14 +410,14 @@ mod tests {
     #[test]
     fn test_first_part() {
         let data = include_str!("../inputs/test.txt");
-        let result = solve(data, false);
+        let result = solve(data, &Standard);
         assert_eq!(result, 6440);
     }
 
     #[test]
     fn input_first_part() {
         let data = include_str!("../inputs/input.txt");
-        let result = solve(data, false);
+        let result = solve(data, &Standard);
         assert_eq!(result, 251121738);
     }
 
@@ -165,14 +435,160 @@ mod tests {
     #[test]
     fn test_second_part() {
         let data = include_str!("../inputs/test.txt");
-        let result = solve(data, true);
+        let result = solve(data, &JokersWild);
         assert_eq!(result, 5905);
     }
 
     #[test]
     fn input_second_part() {
         let data = include_str!("../inputs/input.txt");
-        let result = solve(data, true);
+        let result = solve(data, &JokersWild);
         assert_eq!(result, 251421071);
     }
+
+    #[test]
+    fn test_hand_types() {
+        let cases = [
+            ("32T3K", HandType::OnePair, HandType::OnePair),
+            ("KK677", HandType::TwoPair, HandType::TwoPair),
+            ("KTJJT", HandType::TwoPair, HandType::FourOfAKind),
+            ("T55J5", HandType::ThreeOfAKind, HandType::FourOfAKind),
+            ("23332", HandType::FullHouse, HandType::FullHouse),
+            ("2233J", HandType::TwoPair, HandType::FullHouse),
+            ("JJJJJ", HandType::FiveOfAKind, HandType::FiveOfAKind),
+            ("2345J", HandType::HighCard, HandType::OnePair),
+            ("AAAAJ", HandType::FourOfAKind, HandType::FiveOfAKind),
+        ];
+        for (cards, standard, jokers) in cases {
+            assert_eq!(Standard.hand_type(cards), standard, "{}", cards);
+            assert_eq!(JokersWild.hand_type(cards), jokers, "{}", cards);
+        }
+    }
+
+    #[test]
+    fn test_hand_ordering() {
+        assert!(Standard.hand("33332") > Standard.hand("2AAAA"));
+        assert!(Standard.hand("77888") > Standard.hand("77788"));
+        assert!(Standard.hand("KK677") > Standard.hand("KTJJT"));
+        assert!(JokersWild.hand("KTJJT") > JokersWild.hand("QQQJA"));
+        assert!(JokersWild.hand("JKKK2") < JokersWild.hand("QQQQ2"));
+    }
+
+    #[test]
+    fn test_configured_rules_match_presets() {
+        let data = include_str!("../inputs/test.txt");
+        assert_eq!(solve(data, &Rules::default()), solve(data, &Standard));
+        let jokers = Rules::default()
+            .with_option("ranks=J23456789TQKA")
+            .with_option("wild=J");
+        assert_eq!(solve(data, &jokers), solve(data, &JokersWild));
+        for cards in ["32T3K", "T55J5", "KK677", "KTJJT", "QQQJA", "JJJJJ"] {
+            assert_eq!(jokers.hand_type(cards), JokersWild.hand_type(cards));
+        }
+    }
+
+    #[test]
+    fn test_custom_deck_and_hand_size() {
+        let rules = Rules::default()
+            .with_option("ranks=abcdef")
+            .with_option("wild=*?")
+            .with_option("size=7");
+        assert_eq!(rules.hand_type("aabbcc*"), HandType::FullHouse);
+        assert_eq!(rules.hand_type("aaabbb?"), HandType::FourOfAKind);
+        assert_eq!(rules.hand_type("aaaa*?b"), HandType::FiveOfAKind);
+        assert_eq!(rules.hand_type("abcdefa"), HandType::OnePair);
+        assert!(rules.hand("fedcbaa") > rules.hand("abcdeff"));
+        assert!(rules.hand("*bcdefa") < rules.hand("abcdef?"));
+        let data = "abcdefa 10\naabbcc* 1\nabcdeff 100";
+        assert_eq!(solve(data, &rules), 10 + 2 * 100 + 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "doesn't have 7 cards")]
+    fn test_wrong_hand_size() {
+        Rules::default().with_option("size=7").hand("23456");
+    }
+
+    #[test]
+    fn test_straights() {
+        let rules = Rules::default().with_option("straights");
+        assert_eq!(rules.hand_type("23456"), HandType::Straight);
+        assert_eq!(rules.hand_type("TJQKA"), HandType::Straight);
+        assert_eq!(rules.hand_type("3456A"), HandType::HighCard);
+        assert_eq!(Standard.hand_type("23456"), HandType::HighCard);
+        let rules = rules.with_option("wild=J");
+        assert_eq!(rules.hand_type("2J456"), HandType::Straight);
+        assert_eq!(rules.hand_type("22J45"), HandType::ThreeOfAKind);
+        assert_eq!(rules.hand_type("22J33"), HandType::FullHouse);
+        assert!(rules.hand("23456") > rules.hand("KKKQA"));
+    }
+
+    #[test]
+    fn test_report() {
+        let data = include_str!("../inputs/test.txt");
+        let games = report(data, &JokersWild);
+        let summary = games
+            .iter()
+            .map(|game| (game.cards, game.promoted.as_str(), game.rank, game.winnings))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                ("32T3K", "32T3K", 1, 765),
+                ("KK677", "KK677", 2, 56),
+                ("T55J5", "T5555", 3, 2052),
+                ("QQQJA", "QQQQA", 4, 1932),
+                ("KTJJT", "KTTTT", 5, 1100),
+            ]
+        );
+        assert_eq!(games[4].hand.hand_type, HandType::FourOfAKind);
+        assert_eq!(games[4].bet, 220);
+        let winnings = games.iter().map(|game| game.winnings).sum::<u64>();
+        assert_eq!(winnings, solve(data, &JokersWild));
+    }
+
+    #[test]
+    fn test_promotion() {
+        assert_eq!(JokersWild.promote("JJJJJ"), "JJJJJ");
+        assert_eq!(JokersWild.promote("2233J"), "22333");
+        assert_eq!(Standard.promote("2233J"), "2233J");
+        let rules = Rules::default()
+            .with_option("wild=J")
+            .with_option("straights");
+        assert_eq!(rules.promote("2J456"), "23456");
+        // J is both the wildcard and a rank, so a wildcard filling the jack's
+        // place in the straight is "promoted" to its own symbol
+        assert_eq!(rules.promote("JQKAJ"), "TQKAJ");
+    }
+
+    #[test]
+    fn test_explain() {
+        assert_eq!(
+            explain(&Standard, "KK677", "KTJJT"),
+            (
+                Ordering::Greater,
+                Explanation::Card {
+                    position: 1,
+                    stronger: 'K',
+                    weaker: 'T'
+                }
+            )
+        );
+        assert_eq!(
+            explain(&JokersWild, "KK677", "KTJJT"),
+            (
+                Ordering::Less,
+                Explanation::HandType {
+                    stronger: HandType::FourOfAKind,
+                    weaker: HandType::TwoPair
+                }
+            )
+        );
+        assert_eq!(
+            explain(&Standard, "T55J5", "T55J5"),
+            (Ordering::Equal, Explanation::Tie)
+        );
+        let (_, explanation) = explain(&JokersWild, "JKKK2", "QQQQ2");
+        assert_eq!(explanation.to_string(), "card 1 decides, Q beats J");
+    }
 }
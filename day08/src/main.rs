@@ -2,107 +2,517 @@ use std::collections::HashMap;
 
 const START: &str = "AAA";
 const FINISH: &str = "ZZZ";
+const GHOST_START: &str = "*A";
+const GHOST_FINISH: &str = "*Z";
 
+#[derive(Clone, Copy)]
 enum Direction {
     Left,
     Right,
 }
 
-impl From<char> for Direction {
-    fn from(value: char) -> Self {
+impl TryFrom<char> for Direction {
+    type Error = char;
+
+    fn try_from(value: char) -> Result<Self, Self::Error> {
         match value {
-            'L' => Direction::Left,
-            'R' => Direction::Right,
-            _ => panic!("Unknown direction {}", value),
+            'L' => Ok(Direction::Left),
+            'R' => Ok(Direction::Right),
+            _ => Err(value),
         }
     }
 }
 
-fn first_part(input: &str) -> u32 {
-    let (directions, left_instructions, right_instructions, _) = parse_input(input);
-    let mut current_step = START;
-    let mut directions = directions.into_iter().cycle().enumerate();
-    while current_step != FINISH {
-        let direction = directions.next().unwrap();
-        current_step = make_step(
-            current_step,
-            direction.1.into(),
-            &left_instructions,
-            &right_instructions,
-        );
+#[derive(Debug, PartialEq)]
+enum NetworkError {
+    WrongFormat(String),
+    DuplicateNode(String),
+    UndefinedNode(String),
+}
+
+struct Network<'a> {
+    directions: Vec<Direction>,
+    names: Vec<&'a str>,
+    ids: HashMap<&'a str, u32>,
+    nodes: Vec<[u32; 2]>,
+}
+
+impl<'a> Network<'a> {
+    fn parse(input: &'a str) -> Result<Network<'a>, NetworkError> {
+        let mut lines = input.lines();
+        let directions = lines
+            .next()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .ok_or_else(|| NetworkError::WrongFormat("No directions".to_string()))?
+            .chars()
+            .map(|direction| {
+                Direction::try_from(direction)
+                    .map_err(|c| NetworkError::WrongFormat(format!("Unknown direction {}", c)))
+            })
+            .collect::<Result<_, _>>()?;
+        let mut definitions = Vec::new();
+        for line in lines.filter(|line| !line.trim().is_empty()) {
+            let wrong_format = || NetworkError::WrongFormat(line.to_string());
+            let (begin, targets) = line.split_once('=').ok_or_else(wrong_format)?;
+            let (left, right) = targets
+                .trim()
+                .strip_prefix('(')
+                .and_then(|targets| targets.strip_suffix(')'))
+                .and_then(|targets| targets.split_once(','))
+                .ok_or_else(wrong_format)?;
+            definitions.push((begin.trim(), [left.trim(), right.trim()]));
+        }
+        let mut ids = HashMap::new();
+        let mut names = Vec::with_capacity(definitions.len());
+        for (begin, _) in &definitions {
+            if ids.insert(*begin, names.len() as u32).is_some() {
+                return Err(NetworkError::DuplicateNode(begin.to_string()));
+            }
+            names.push(*begin);
+        }
+        let id = |name: &str| {
+            ids.get(name)
+                .copied()
+                .ok_or_else(|| NetworkError::UndefinedNode(name.to_string()))
+        };
+        let nodes = definitions
+            .iter()
+            .map(|(_, [left, right])| Ok([id(left)?, id(right)?]))
+            .collect::<Result<_, _>>()?;
+        Ok(Network {
+            directions,
+            names,
+            ids,
+            nodes,
+        })
+    }
+
+    fn id(&self, name: &str) -> u32 {
+        *self
+            .ids
+            .get(name)
+            .unwrap_or_else(|| panic!("Unknown node {}", name))
+    }
+
+    fn step(&self, node: u32, direction: Direction) -> u32 {
+        self.nodes[node as usize][direction as usize]
+    }
+}
+
+fn parse_input(input: &str) -> Network<'_> {
+    Network::parse(input).unwrap_or_else(|err| panic!("Can't parse network: {:?}", err))
+}
+
+// Node names matched exactly, by `*` prefix/suffix patterns or by any predicate.
+enum NodePattern {
+    Exact(String),
+    Prefix(String),
+    Suffix(String),
+    Predicate(Box<dyn Fn(&str) -> bool>),
+}
+
+impl From<&str> for NodePattern {
+    fn from(value: &str) -> Self {
+        if let Some(suffix) = value.strip_prefix('*') {
+            NodePattern::Suffix(suffix.to_string())
+        } else if let Some(prefix) = value.strip_suffix('*') {
+            NodePattern::Prefix(prefix.to_string())
+        } else if let Some((prefix, suffix)) = value.split_once('*') {
+            let (prefix, suffix) = (prefix.to_string(), suffix.to_string());
+            NodePattern::Predicate(Box::new(move |name| {
+                name.len() >= prefix.len() + suffix.len()
+                    && name.starts_with(prefix.as_str())
+                    && name.ends_with(suffix.as_str())
+            }))
+        } else {
+            NodePattern::Exact(value.to_string())
+        }
+    }
+}
+
+impl NodePattern {
+    fn matches(&self, name: &str) -> bool {
+        match self {
+            NodePattern::Exact(exact) => name == exact,
+            NodePattern::Prefix(prefix) => name.starts_with(prefix.as_str()),
+            NodePattern::Suffix(suffix) => name.ends_with(suffix.as_str()),
+            NodePattern::Predicate(predicate) => predicate(name),
+        }
+    }
+
+    fn nodes(&self, network: &Network) -> Vec<u32> {
+        (0..network.nodes.len() as u32)
+            .filter(|&id| self.matches(network.names[id as usize]))
+            .collect()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum WalkError {
+    StepLimit(u64),
+}
+
+struct Walk {
+    steps: u64,
+    path: Option<Vec<u32>>,
+}
+
+// Without a limit the walk stops once it has been in every (node, instruction)
+// state, because from then on it can only repeat itself.
+fn walk(
+    network: &Network,
+    start: u32,
+    finish: &NodePattern,
+    limit: Option<u64>,
+    trace: bool,
+) -> Result<Walk, WalkError> {
+    let limit = limit.unwrap_or((network.nodes.len() * network.directions.len()) as u64);
+    let mut path = vec![start];
+    let mut node = start;
+    let mut directions = network.directions.iter().cycle();
+    let mut steps = 0;
+    while !finish.matches(network.names[node as usize]) {
+        if steps == limit {
+            return Err(WalkError::StepLimit(limit));
+        }
+        node = network.step(node, *directions.next().unwrap());
+        steps += 1;
+        if trace {
+            path.push(node);
+        }
+    }
+    Ok(Walk {
+        steps,
+        path: trace.then_some(path),
+    })
+}
+
+fn first_part(input: &str) -> u64 {
+    let network = parse_input(input);
+    walk(&network, network.id(START), &FINISH.into(), None, false)
+        .unwrap_or_else(|err| panic!("{} is unreachable from {}: {:?}", FINISH, START, err))
+        .steps
+}
+
+// Steps at which a ghost stands on a finish node. Ghost walks a tail of
+// `cycle_start` steps and then repeats the same `cycle_length` steps forever.
+struct GhostCycle {
+    tail_hits: Vec<u64>,
+    cycle_start: u64,
+    cycle_length: u64,
+    cycle_hits: Vec<u64>,
+}
+
+impl GhostCycle {
+    fn new(network: &Network, start: u32, finish: &NodePattern) -> GhostCycle {
+        let len = network.directions.len();
+        let mut first_seen = vec![None; network.nodes.len() * len];
+        let mut hits = Vec::new();
+        let (mut node, mut step) = (start, 0u64);
+        let cycle_start = loop {
+            let state = node as usize * len + step as usize % len;
+            if let Some(seen) = first_seen[state] {
+                break seen;
+            }
+            first_seen[state] = Some(step);
+            if finish.matches(network.names[node as usize]) {
+                hits.push(step);
+            }
+            node = network.step(node, network.directions[step as usize % len]);
+            step += 1;
+        };
+        let (tail_hits, cycle_hits) = hits.into_iter().partition(|&hit| hit < cycle_start);
+        GhostCycle {
+            tail_hits,
+            cycle_start,
+            cycle_length: step - cycle_start,
+            cycle_hits,
+        }
+    }
+
+    fn is_hit(&self, step: u64) -> bool {
+        if step < self.cycle_start {
+            return self.tail_hits.contains(&step);
+        }
+        let in_cycle = self.cycle_start + (step - self.cycle_start) % self.cycle_length;
+        self.cycle_hits.contains(&in_cycle)
+    }
+}
+
+fn extended_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    if b == 0 {
+        return (a, 1, 0);
+    }
+    let (g, x, y) = extended_gcd(b, a % b);
+    (g, y, x - (a / b) * y)
+}
+
+#[derive(Debug, PartialEq)]
+enum GhostError {
+    Never,
+    Overflow,
+}
+
+// Merges x = a1 (mod m1) and x = a2 (mod m2) into a single x = a (mod lcm(m1, m2)).
+// Fails with `Never` when the two congruences conflict and with `Overflow` when
+// the merged modulus doesn't fit into u64.
+fn combine_congruences(
+    (a1, m1): (u64, u64),
+    (a2, m2): (u64, u64),
+) -> Result<(u64, u64), GhostError> {
+    let g = gcd(m1, m2);
+    let diff = a2 as i128 - a1 as i128;
+    if diff % g as i128 != 0 {
+        return Err(GhostError::Never);
+    }
+    let modulus = lcm(m1, m2).ok_or(GhostError::Overflow)?;
+    let (m1_g, m2_g) = (m1 as i128 / g as i128, m2 as i128 / g as i128);
+    let (_, inverse, _) = extended_gcd(m1_g.rem_euclid(m2_g), m2_g);
+    // both factors are below m2 / g, so their product fits into u128
+    let k = (diff / g as i128).rem_euclid(m2_g) as u128 * inverse.rem_euclid(m2_g) as u128
+        % m2_g as u128;
+    let x = (a1 as u128 + m1 as u128 * k) % modulus as u128;
+    Ok((x as u64, modulus))
+}
+
+fn ghost_cycles(network: &Network, start: &NodePattern, finish: &NodePattern) -> Vec<GhostCycle> {
+    start
+        .nodes(network)
+        .into_iter()
+        .map(|start| GhostCycle::new(network, start, finish))
+        .collect()
+}
+
+fn ghosts_meet(ghosts: &[GhostCycle]) -> Result<u64, GhostError> {
+    let tail_end = ghosts
+        .iter()
+        .map(|ghost| ghost.cycle_start)
+        .max()
+        .ok_or(GhostError::Never)?;
+    if let Some(step) = (0..tail_end).find(|&step| ghosts.iter().all(|g| g.is_hit(step))) {
+        return Ok(step);
     }
-    directions.next().unwrap().0 as u32
-}
-
-fn make_step<'a>(
-    begin: &'a str,
-    direction: Direction,
-    left: &'a HashMap<&'a str, &'a str>,
-    right: &'a HashMap<&'a str, &'a str>,
-) -> &'a str {
-    match direction {
-        Direction::Left => left.get(begin).unwrap(),
-        Direction::Right => right.get(begin).unwrap(),
-    }
-}
-
-fn parse_input(
-    input: &str,
-) -> (
-    std::str::Chars<'_>,
-    HashMap<&str, &str>,
-    HashMap<&str, &str>,
-    Vec<&str>,
-) {
-    let mut lines = input.lines();
-    let directions = lines.next().expect("No directions").chars();
-    _ = lines.next();
-    let mut left_instructions = HashMap::new();
-    let mut right_instructions = HashMap::new();
-    let mut ghost_start = Vec::new();
-    for instruction in lines {
-        let mut tokens = instruction.split_ascii_whitespace();
-        let begin = tokens.next().unwrap();
-        _ = tokens.next();
-        let left = &tokens.next().unwrap()[1..4];
-        let right = &tokens.next().unwrap()[..3];
-        left_instructions.insert(begin, left);
-        right_instructions.insert(begin, right);
-        if begin.ends_with('A') {
-            ghost_start.push(begin)
-        }
-    }
-    (
-        directions,
-        left_instructions,
-        right_instructions,
-        ghost_start,
-    )
+    // every ghost is in its cycle now, so try all combinations of its hits
+    let mut congruences = vec![(0, 1)];
+    for ghost in ghosts {
+        let mut combined = Vec::new();
+        for &congruence in &congruences {
+            for &hit in &ghost.cycle_hits {
+                let cycle = (hit % ghost.cycle_length, ghost.cycle_length);
+                match combine_congruences(congruence, cycle) {
+                    Ok(congruence) => combined.push(congruence),
+                    Err(GhostError::Never) => {}
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+        congruences = combined;
+    }
+    congruences
+        .into_iter()
+        .map(|(a, m)| {
+            tail_end
+                .saturating_sub(a)
+                .div_ceil(m)
+                .checked_mul(m)
+                .and_then(|lift| lift.checked_add(a))
+                .ok_or(GhostError::Overflow)
+        })
+        .min_by_key(|step| (step.is_err(), step.as_ref().ok().copied()))
+        .unwrap_or(Err(GhostError::Never))
 }
 
 fn second_part(input: &str) -> u64 {
-    let (directions, left_instructions, right_instructions, starts) = parse_input(input);
-    let mut shortest_paths = Vec::new();
-    starts.iter().for_each(|begin| {
-        let mut directions = directions.clone().cycle().enumerate();
-        let mut location = *begin;
-        while !location.ends_with('Z') {
-            let direction = directions.next().unwrap();
-            location = make_step(
-                location,
-                direction.1.into(),
-                &left_instructions,
-                &right_instructions,
-            );
+    let network = parse_input(input);
+    let ghosts = ghost_cycles(&network, &GHOST_START.into(), &GHOST_FINISH.into());
+    ghosts_meet(&ghosts).unwrap_or_else(|err| match err {
+        GhostError::Never => panic!("Ghosts never stand on finish nodes at the same time"),
+        GhostError::Overflow => panic!("Ghosts meet only after more than u64::MAX steps"),
+    })
+}
+
+// Nodes a ghost keeps visiting once it is past its tail, in walking order.
+fn cycle_nodes(network: &Network, start: u32, ghost: &GhostCycle) -> Vec<u32> {
+    let mut directions = network.directions.iter().cycle();
+    let mut node = start;
+    let mut nodes = Vec::new();
+    for step in 0..ghost.cycle_start + ghost.cycle_length {
+        if step >= ghost.cycle_start {
+            nodes.push(node);
+        }
+        node = network.step(node, *directions.next().unwrap());
+    }
+    nodes
+}
+
+const GHOST_COLORS: [&str; 6] = ["red", "blue", "green", "orange", "purple", "brown"];
+
+#[derive(Clone, Copy)]
+enum GraphFormat {
+    Dot,
+    Mermaid,
+}
+
+impl From<&str> for GraphFormat {
+    fn from(value: &str) -> Self {
+        match value {
+            "dot" => GraphFormat::Dot,
+            "mermaid" => GraphFormat::Mermaid,
+            _ => panic!("Unknown graph format {}", value),
+        }
+    }
+}
+
+struct GraphEdge {
+    from: u32,
+    to: u32,
+    label: String,
+}
+
+fn successors(network: &Network, node: u32) -> Vec<(u32, &'static str)> {
+    match network.nodes[node as usize] {
+        [left, right] if left == right => vec![(left, "LR")],
+        [left, right] => vec![(left, "L"), (right, "R")],
+    }
+}
+
+// With `collapse` every node that only passes the walk from one node to the next
+// is replaced by a single edge labelled with the number of steps. Kept nodes
+// are never collapsed. Returns the nodes left in the graph and its edges.
+fn graph_edges(
+    network: &Network,
+    keep: &dyn Fn(u32) -> bool,
+    collapse: bool,
+) -> (Vec<u32>, Vec<GraphEdge>) {
+    let count = network.nodes.len();
+    let mut predecessors = vec![Vec::new(); count];
+    for node in 0..count as u32 {
+        for (target, _) in successors(network, node) {
+            predecessors[target as usize].push(node);
+        }
+    }
+    let mut anchors = (0..count as u32)
+        .map(|node| {
+            let targets = successors(network, node);
+            !collapse
+                || keep(node)
+                || targets.len() != 1
+                || targets[0].0 == node
+                || predecessors[node as usize].len() != 1
+        })
+        .collect::<Vec<_>>();
+    let mut visited = vec![false; count];
+    let mut edges = Vec::new();
+    loop {
+        for node in 0..count as u32 {
+            if !anchors[node as usize] || visited[node as usize] {
+                continue;
+            }
+            visited[node as usize] = true;
+            for (mut target, label) in successors(network, node) {
+                let mut steps = 1;
+                while !anchors[target as usize] {
+                    visited[target as usize] = true;
+                    target = network.nodes[target as usize][0];
+                    steps += 1;
+                }
+                let label = match steps {
+                    1 => label.to_string(),
+                    _ => format!("{} ({} steps)", label, steps),
+                };
+                edges.push(GraphEdge {
+                    from: node,
+                    to: target,
+                    label,
+                });
+            }
+        }
+        // a loop made only of collapsible nodes still needs one node to hang on
+        match (0..count).find(|&node| !visited[node]) {
+            Some(node) => anchors[node] = true,
+            None => break,
         }
-        shortest_paths.push(directions.next().unwrap().0)
-    });
-    dbg!(&shortest_paths);
-    shortest_paths
+    }
+    let nodes = (0..count as u32)
+        .filter(|&node| anchors[node as usize])
+        .collect();
+    (nodes, edges)
+}
+
+fn export_graph(
+    network: &Network,
+    start: &NodePattern,
+    finish: &NodePattern,
+    format: GraphFormat,
+    collapse: bool,
+) -> String {
+    let mut colors = HashMap::new();
+    let starts = start.nodes(network);
+    for (index, (&node, ghost)) in starts
         .iter()
-        .map(|n| *n as u64)
-        .reduce(lcm)
-        .unwrap()
+        .zip(ghost_cycles(network, start, finish))
+        .enumerate()
+    {
+        for cycle_node in cycle_nodes(network, node, &ghost) {
+            colors
+                .entry(cycle_node)
+                .or_insert(GHOST_COLORS[index % GHOST_COLORS.len()]);
+        }
+    }
+    let is_start = |node: u32| start.matches(network.names[node as usize]);
+    let is_finish = |node: u32| finish.matches(network.names[node as usize]);
+    let (nodes, edges) = graph_edges(network, &|node| is_start(node) || is_finish(node), collapse);
+    let name = |node: u32| network.names[node as usize];
+    let mut lines = Vec::new();
+    match format {
+        GraphFormat::Dot => {
+            lines.push("digraph network {".to_string());
+            for node in nodes {
+                let mut attributes = Vec::new();
+                if is_start(node) {
+                    attributes.push("shape=box".to_string());
+                } else if is_finish(node) {
+                    attributes.push("shape=doublecircle".to_string());
+                }
+                if let Some(color) = colors.get(&node) {
+                    attributes.push(format!("style=filled, fillcolor={}", color));
+                }
+                match attributes.is_empty() {
+                    true => lines.push(format!("  \"{}\";", name(node))),
+                    false => {
+                        lines.push(format!("  \"{}\" [{}];", name(node), attributes.join(", ")))
+                    }
+                }
+            }
+            for edge in edges {
+                lines.push(format!(
+                    "  \"{}\" -> \"{}\" [label=\"{}\"];",
+                    name(edge.from),
+                    name(edge.to),
+                    edge.label
+                ));
+            }
+            lines.push("}".to_string());
+        }
+        GraphFormat::Mermaid => {
+            lines.push("flowchart LR".to_string());
+            for node in nodes {
+                match (is_start(node), is_finish(node)) {
+                    (true, _) => lines.push(format!("  n{}[[\"{}\"]]", node, name(node))),
+                    (_, true) => lines.push(format!("  n{}(((\"{}\")))", node, name(node))),
+                    _ => lines.push(format!("  n{}[\"{}\"]", node, name(node))),
+                }
+                if let Some(color) = colors.get(&node) {
+                    lines.push(format!("  style n{} fill:{}", node, color));
+                }
+            }
+            for edge in edges {
+                lines.push(format!("  n{} -->|{}| n{}", edge.from, edge.label, edge.to));
+            }
+        }
+    }
+    lines.join("\n")
 }
 
 fn gcd(mut a: u64, mut b: u64) -> u64 {
@@ -112,8 +522,8 @@ fn gcd(mut a: u64, mut b: u64) -> u64 {
     a
 }
 
-fn lcm(a: u64, b: u64) -> u64 {
-    (a * b) / (gcd(a, b))
+fn lcm(a: u64, b: u64) -> Option<u64> {
+    (a / gcd(a, b)).checked_mul(b)
 }
 
 fn main() {
@@ -122,6 +532,53 @@ fn main() {
     println!("First part: {}", first_part);
     let second_part = second_part(input);
     println!("Second part: {}", second_part);
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let option = |name: &str| args.iter().find_map(|arg| arg.strip_prefix(name));
+    let network = parse_input(input);
+    if args.iter().any(|arg| arg == "walk") {
+        let start = NodePattern::from(option("start=").unwrap_or(START));
+        let finish = NodePattern::from(option("finish=").unwrap_or(FINISH));
+        let limit = option("limit=").map(|limit| limit.parse().expect("Limit is not a number"));
+        let trace = args.iter().any(|arg| arg == "trace");
+        for node in start.nodes(&network) {
+            let name = network.names[node as usize];
+            match walk(&network, node, &finish, limit, trace) {
+                Ok(walk) => {
+                    println!("{}: {} steps", name, walk.steps);
+                    if let Some(path) = walk.path {
+                        let path = path.iter().map(|&id| network.names[id as usize]);
+                        println!("  {}", path.collect::<Vec<_>>().join(" -> "));
+                    }
+                }
+                Err(err) => println!("{}: never finishes ({:?})", name, err),
+            }
+        }
+    }
+    if let Some(format) = args.iter().find(|arg| *arg == "dot" || *arg == "mermaid") {
+        let start = NodePattern::from(option("start=").unwrap_or(GHOST_START));
+        let finish = NodePattern::from(option("finish=").unwrap_or(GHOST_FINISH));
+        let collapse = args.iter().any(|arg| arg == "collapse");
+        let graph = export_graph(&network, &start, &finish, format.as_str().into(), collapse);
+        println!("{}", graph);
+    }
+    if args.iter().any(|arg| arg == "verbose") {
+        let start = NodePattern::from(option("start=").unwrap_or(GHOST_START));
+        let finish = NodePattern::from(option("finish=").unwrap_or(GHOST_FINISH));
+        for (node, ghost) in start
+            .nodes(&network)
+            .iter()
+            .zip(ghost_cycles(&network, &start, &finish))
+        {
+            println!(
+                "Ghost {}: tail {} steps with hits {:?}, cycle of {} steps with hits {:?}",
+                network.names[*node as usize],
+                ghost.cycle_start,
+                ghost.tail_hits,
+                ghost.cycle_length,
+                ghost.cycle_hits
+            );
+        }
+    }
 }
 
 #[cfg(test)]
@@ -165,10 +622,219 @@ mod tests {
 
     #[test]
     fn test_lcm() {
-        assert_eq!(lcm(5, 15), 15);
-        assert_eq!(lcm(15, 5), 15);
-        assert_eq!(lcm(12, 18), 36);
-        assert_eq!(lcm(18, 12), 36);
-        assert_eq!(lcm(2, 3), 6);
+        assert_eq!(lcm(5, 15), Some(15));
+        assert_eq!(lcm(15, 5), Some(15));
+        assert_eq!(lcm(12, 18), Some(36));
+        assert_eq!(lcm(18, 12), Some(36));
+        assert_eq!(lcm(2, 3), Some(6));
+    }
+
+    #[test]
+    fn test_network_parse() {
+        let data = include_str!("../inputs/test.txt");
+        let network = parse_input(data);
+        assert_eq!(network.names, vec!["AAA", "BBB", "ZZZ"]);
+        assert_eq!(network.nodes, vec![[1, 1], [0, 2], [2, 2]]);
+        assert_eq!(network.step(1, Direction::Right), network.id("ZZZ"));
+    }
+
+    #[test]
+    fn test_network_names_of_any_length() {
+        let data = "RL\n\nSTART = (MIDDLE, X)\nMIDDLE = (END, X)\nX = (X, X)\nEND = (END, END)";
+        let network = parse_input(data);
+        let mut node = network.id("START");
+        for direction in ['L', 'L'] {
+            node = network.step(node, direction.try_into().unwrap());
+        }
+        assert_eq!(network.names[node as usize], "END");
+    }
+
+    #[test]
+    fn test_network_validation() {
+        assert_eq!(
+            Network::parse("LR\n\nAAA = (BBB, AAA)").err(),
+            Some(NetworkError::UndefinedNode("BBB".to_string()))
+        );
+        assert_eq!(
+            Network::parse("LR\n\nAAA = (AAA, AAA)\nAAA = (AAA, AAA)").err(),
+            Some(NetworkError::DuplicateNode("AAA".to_string()))
+        );
+        assert_eq!(
+            Network::parse("LXR\n\nAAA = (AAA, AAA)").err(),
+            Some(NetworkError::WrongFormat("Unknown direction X".to_string()))
+        );
+        assert_eq!(
+            Network::parse("\n\nAAA = (AAA, AAA)").err(),
+            Some(NetworkError::WrongFormat("No directions".to_string()))
+        );
+        assert_eq!(
+            Network::parse("").err(),
+            Some(NetworkError::WrongFormat("No directions".to_string()))
+        );
+        assert_eq!(
+            Network::parse("LR\n\nAAA = AAA, AAA").err(),
+            Some(NetworkError::WrongFormat("AAA = AAA, AAA".to_string()))
+        );
+    }
+
+    fn solve_ghosts(input: &str) -> Result<u64, GhostError> {
+        let network = parse_input(input);
+        let ghosts = ghost_cycles(&network, &GHOST_START.into(), &GHOST_FINISH.into());
+        ghosts_meet(&ghosts)
+    }
+
+    #[test]
+    fn test_ghost_cycle() {
+        let data = include_str!("../inputs/test_second.txt");
+        let network = parse_input(data);
+        let ghost = GhostCycle::new(&network, network.id("22A"), &"22Z".into());
+        assert_eq!(ghost.tail_hits, Vec::<u64>::new());
+        assert_eq!((ghost.cycle_start, ghost.cycle_length), (1, 6));
+        assert_eq!(ghost.cycle_hits, vec![3, 6]);
+        assert!(ghost.is_hit(9) && ghost.is_hit(12) && !ghost.is_hit(10));
+    }
+
+    #[test]
+    fn test_ghosts_with_unaligned_cycles() {
+        // lcm of first hits (1 and 2) would give 2, but 1Z is only reached at 1, 4, 7, ...
+        let data = "L\n\n1A = (1Z, 1Z)\n1Z = (1B, 1B)\n1B = (1C, 1C)\n1C = (1Z, 1Z)\n\
+                    2A = (2B, 2B)\n2B = (2Z, 2Z)\n2Z = (2B, 2B)";
+        assert_eq!(solve_ghosts(data), Ok(4));
+    }
+
+    #[test]
+    fn test_ghosts_meeting_in_tail() {
+        let data = "L\n\n1A = (1Z, 1Z)\n1Z = (1B, 1B)\n1B = (1B, 1B)\n\
+                    2A = (2Z, 2Z)\n2Z = (2Z, 2Z)";
+        assert_eq!(solve_ghosts(data), Ok(1));
+    }
+
+    #[test]
+    fn test_ghosts_never_meet() {
+        let data = "L\n\n1A = (1Z, 1Z)\n1Z = (1B, 1B)\n1B = (1Z, 1Z)\n\
+                    2A = (2B, 2B)\n2B = (2Z, 2Z)\n2Z = (2B, 2B)";
+        assert_eq!(solve_ghosts(data), Err(GhostError::Never));
+        assert_eq!(ghosts_meet(&[]), Err(GhostError::Never));
+    }
+
+    #[test]
+    fn test_ghosts_meet_overflow() {
+        let ghost = |cycle_length: u64| GhostCycle {
+            tail_hits: Vec::new(),
+            cycle_start: 0,
+            cycle_length,
+            cycle_hits: vec![cycle_length - 1],
+        };
+        let ghosts = [ghost(4294967291), ghost(4294967279), ghost(65521)];
+        assert_eq!(ghosts_meet(&ghosts), Err(GhostError::Overflow));
+        // x = -1 (mod both), so the answer is their product minus one
+        let ghosts = [ghost(4294967291), ghost(4294967279)];
+        assert_eq!(ghosts_meet(&ghosts), Ok(4294967291 * 4294967279 - 1));
+        assert_eq!(lcm(u64::MAX, 2), None);
+    }
+
+    #[test]
+    fn test_node_patterns() {
+        let data = include_str!("../inputs/test_second.txt");
+        let network = parse_input(data);
+        let names = |pattern: &NodePattern| {
+            let nodes = pattern.nodes(&network).into_iter();
+            nodes
+                .map(|id| network.names[id as usize])
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&"*A".into()), vec!["11A", "22A"]);
+        assert_eq!(names(&"22*".into()), vec!["22A", "22B", "22C", "22Z"]);
+        assert_eq!(names(&"XXX".into()), vec!["XXX"]);
+        assert_eq!(names(&"2*Z".into()), vec!["22Z"]);
+        let digits = NodePattern::Predicate(Box::new(|name| name.starts_with("11")));
+        assert_eq!(names(&digits).len(), 3);
+    }
+
+    #[test]
+    fn test_walk_trace_and_limit() {
+        let data = include_str!("../inputs/test.txt");
+        let network = parse_input(data);
+        let start = network.id(START);
+        let result = walk(&network, start, &FINISH.into(), None, true).unwrap();
+        assert_eq!(result.steps, 6);
+        let path = result.path.unwrap();
+        let names = path
+            .iter()
+            .map(|&id| network.names[id as usize])
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["AAA", "BBB", "AAA", "BBB", "AAA", "BBB", "ZZZ"]);
+        assert_eq!(
+            walk(&network, start, &FINISH.into(), Some(5), false).err(),
+            Some(WalkError::StepLimit(5))
+        );
+    }
+
+    #[test]
+    fn test_walk_unreachable_finish() {
+        let data = "LR\n\nAAA = (BBB, BBB)\nBBB = (AAA, AAA)\nZZZ = (ZZZ, ZZZ)";
+        let network = parse_input(data);
+        let result = walk(&network, network.id(START), &FINISH.into(), None, false);
+        assert_eq!(result.err(), Some(WalkError::StepLimit(6)));
+    }
+
+    #[test]
+    fn test_export_dot() {
+        let data = include_str!("../inputs/test_second.txt");
+        let network = parse_input(data);
+        let graph = export_graph(
+            &network,
+            &GHOST_START.into(),
+            &GHOST_FINISH.into(),
+            GraphFormat::Dot,
+            false,
+        );
+        let lines = graph.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "digraph network {");
+        assert!(lines.contains(&"  \"11A\" [shape=box];"));
+        assert!(lines.contains(&"  \"11Z\" [shape=doublecircle, style=filled, fillcolor=red];"));
+        assert!(lines.contains(&"  \"22C\" [style=filled, fillcolor=blue];"));
+        assert!(lines.contains(&"  \"XXX\";"));
+        assert!(lines.contains(&"  \"11B\" -> \"11Z\" [label=\"R\"];"));
+        assert!(lines.contains(&"  \"22B\" -> \"22C\" [label=\"LR\"];"));
+        assert_eq!(lines.len(), 2 + 8 + 12);
+    }
+
+    #[test]
+    fn test_export_collapsed_chains() {
+        let data = include_str!("../inputs/test_second.txt");
+        let network = parse_input(data);
+        let graph = export_graph(
+            &network,
+            &GHOST_START.into(),
+            &GHOST_FINISH.into(),
+            GraphFormat::Mermaid,
+            true,
+        );
+        let lines = graph.lines().collect::<Vec<_>>();
+        assert!(lines.contains(&"  n3[[\"22A\"]]"));
+        assert!(lines.contains(&"  n6(((\"22Z\")))"));
+        assert!(lines.contains(&"  n4 -->|LR (2 steps)| n6"));
+        assert!(!lines.iter().any(|line| line.starts_with("  n5[")));
+        // a loop of collapsible nodes keeps one of them
+        let data = "L\n\nAAA = (BBB, BBB)\nBBB = (CCC, CCC)\nCCC = (AAA, AAA)";
+        let network = parse_input(data);
+        let (nodes, edges) = graph_edges(&network, &|_| false, true);
+        assert_eq!(nodes, vec![0]);
+        assert_eq!(edges.len(), 1);
+        assert_eq!((edges[0].from, edges[0].to), (0, 0));
+        assert_eq!(edges[0].label, "LR (3 steps)");
+    }
+
+    #[test]
+    fn test_combine_congruences() {
+        assert_eq!(combine_congruences((2, 3), (3, 5)), Ok((8, 15)));
+        assert_eq!(combine_congruences((1, 4), (3, 6)), Ok((9, 12)));
+        assert_eq!(combine_congruences((1, 4), (2, 6)), Err(GhostError::Never));
+        let large = (u64::MAX - 1, u64::MAX);
+        assert_eq!(
+            combine_congruences(large, (1, 2)),
+            Err(GhostError::Overflow)
+        );
     }
 }
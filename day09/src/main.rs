@@ -1,53 +1,352 @@
-fn first_part(input: &str) -> i64 {
-    input
-        .lines()
-        .map(|line| {
-            line.split_ascii_whitespace()
-                .rev()
-                .fold((0, Vec::new()), |(sum, diffs), number| {
-                    if let Some(0) = diffs.last() {
-                        return (sum, diffs);
-                    }
-                    let mut number = number.parse::<i64>().expect("Not a i64 number");
-                    let mut new_diffs = Vec::new();
-                    for diff in &diffs {
-                        new_diffs.push(number);
-                        number = diff - number;
-                    }
-                    new_diffs.push(number);
-                    (sum + number, new_diffs)
-                })
+#[derive(Debug, PartialEq)]
+enum SequenceError {
+    Empty,
+    InvalidNumber(String),
+    NoConstantRow,
+    Overflow,
+}
+
+// Leading entry of every row of the difference table, the last row is the
+// constant one. `len` is the number of values the table was built from.
+struct DifferenceTable {
+    leading: Vec<i128>,
+    len: usize,
+}
+
+impl DifferenceTable {
+    fn new(values: &[i128]) -> Result<DifferenceTable, SequenceError> {
+        if values.is_empty() {
+            return Err(SequenceError::Empty);
+        }
+        let mut leading = Vec::new();
+        let mut row = values.to_vec();
+        while row.iter().any(|&value| value != 0) {
+            if row.len() == 1 {
+                return Err(SequenceError::NoConstantRow);
+            }
+            leading.push(row[0]);
+            row = row
+                .windows(2)
+                .map(|pair| pair[1].checked_sub(pair[0]).ok_or(SequenceError::Overflow))
+                .collect::<Result<_, _>>()?;
+        }
+        if leading.is_empty() {
+            leading.push(0);
+        }
+        Ok(DifferenceTable {
+            leading,
+            len: values.len(),
         })
-        .map(|(sum, _)| sum)
-        .sum()
+    }
+
+    fn degree(&self) -> usize {
+        self.leading.len() - 1
+    }
+
+    // Newton's forward difference formula, sum of C(index, k) * leading[k].
+    // Binomials are generalized, so negative indices extrapolate backwards.
+    fn value_at(&self, index: i128) -> Result<i128, SequenceError> {
+        let mut binomial = 1i128;
+        let mut value = 0i128;
+        for (k, &difference) in self.leading.iter().enumerate() {
+            if k > 0 {
+                binomial = binomial
+                    .checked_mul(index - k as i128 + 1)
+                    .ok_or(SequenceError::Overflow)?
+                    / k as i128;
+            }
+            value = binomial
+                .checked_mul(difference)
+                .and_then(|term| value.checked_add(term))
+                .ok_or(SequenceError::Overflow)?;
+        }
+        Ok(value)
+    }
+
+    fn forward(&self, steps: u64) -> Result<i128, SequenceError> {
+        self.value_at(self.len as i128 - 1 + steps as i128)
+    }
+
+    fn backward(&self, steps: u64) -> Result<i128, SequenceError> {
+        self.value_at(-(steps as i128))
+    }
+}
+
+fn gcd(mut a: i128, mut b: i128) -> i128 {
+    while b != 0 {
+        (a, b) = (b, a % b)
+    }
+    a.abs()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Rational {
+    numerator: i128,
+    denominator: i128,
+}
+
+impl Rational {
+    fn new(numerator: i128, denominator: i128) -> Rational {
+        let divisor = gcd(numerator, denominator) * denominator.signum();
+        Rational {
+            numerator: numerator / divisor,
+            denominator: denominator / divisor,
+        }
+    }
+
+    fn integer(value: i128) -> Rational {
+        Rational::new(value, 1)
+    }
+
+    fn checked_add(self, other: Rational) -> Option<Rational> {
+        let divisor = gcd(self.denominator, other.denominator);
+        let numerator = self
+            .numerator
+            .checked_mul(other.denominator / divisor)?
+            .checked_add(other.numerator.checked_mul(self.denominator / divisor)?)?;
+        let denominator = self.denominator.checked_mul(other.denominator / divisor)?;
+        Some(Rational::new(numerator, denominator))
+    }
+
+    fn checked_mul(self, other: Rational) -> Option<Rational> {
+        let first = gcd(self.numerator, other.denominator).max(1);
+        let second = gcd(other.numerator, self.denominator).max(1);
+        let numerator = (self.numerator / first).checked_mul(other.numerator / second)?;
+        let denominator = (self.denominator / second).checked_mul(other.denominator / first)?;
+        Some(Rational::new(numerator, denominator))
+    }
+}
+
+impl std::fmt::Display for Rational {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.denominator {
+            1 => write!(f, "{}", self.numerator),
+            _ => write!(f, "{}/{}", self.numerator, self.denominator),
+        }
+    }
+}
+
+// Coefficients from the constant term up, `x` is the position in the history.
+#[derive(Debug, PartialEq)]
+struct Polynomial {
+    coefficients: Vec<Rational>,
+}
+
+impl Polynomial {
+    // Expands the Newton form, sum of leading[k] * x(x - 1)..(x - k + 1) / k!.
+    fn from_newton(leading: &[i128]) -> Result<Polynomial, SequenceError> {
+        let overflow = || SequenceError::Overflow;
+        let mut coefficients = vec![Rational::integer(0); leading.len().max(1)];
+        let mut falling = vec![1i128];
+        let mut factorial = 1i128;
+        for (k, &difference) in leading.iter().enumerate() {
+            if k > 0 {
+                factorial = factorial.checked_mul(k as i128).ok_or_else(overflow)?;
+                // multiply by (x - k + 1)
+                let mut next = vec![0i128; falling.len() + 1];
+                for (power, &coefficient) in falling.iter().enumerate() {
+                    next[power + 1] += coefficient;
+                    next[power] = coefficient
+                        .checked_mul(-(k as i128 - 1))
+                        .and_then(|term| next[power].checked_add(term))
+                        .ok_or_else(overflow)?;
+                }
+                falling = next;
+            }
+            let scale = Rational::new(difference, factorial);
+            for (power, &coefficient) in falling.iter().enumerate() {
+                coefficients[power] = scale
+                    .checked_mul(Rational::integer(coefficient))
+                    .and_then(|term| coefficients[power].checked_add(term))
+                    .ok_or_else(overflow)?;
+            }
+        }
+        while coefficients.len() > 1 && coefficients.last() == Some(&Rational::integer(0)) {
+            coefficients.pop();
+        }
+        Ok(Polynomial { coefficients })
+    }
+
+    fn from_table(table: &DifferenceTable) -> Result<Polynomial, SequenceError> {
+        Polynomial::from_newton(&table.leading)
+    }
+
+    // Unlike `DifferenceTable` this never stops early, so it fits every history,
+    // even one without a constant row.
+    fn interpolate(values: &[i128]) -> Result<Polynomial, SequenceError> {
+        let mut leading = Vec::new();
+        let mut row = values.to_vec();
+        while let Some(&first) = row.first() {
+            leading.push(first);
+            row = row
+                .windows(2)
+                .map(|pair| pair[1].checked_sub(pair[0]).ok_or(SequenceError::Overflow))
+                .collect::<Result<_, _>>()?;
+        }
+        Polynomial::from_newton(&leading)
+    }
+
+    fn degree(&self) -> usize {
+        self.coefficients.len() - 1
+    }
+
+    fn evaluate(&self, x: i128) -> Result<Rational, SequenceError> {
+        self.coefficients
+            .iter()
+            .rev()
+            .try_fold(Rational::integer(0), |value, &coefficient| {
+                value
+                    .checked_mul(Rational::integer(x))
+                    .and_then(|value| value.checked_add(coefficient))
+            })
+            .ok_or(SequenceError::Overflow)
+    }
 }
 
-fn second_part(input: &str) -> i64 {
+impl std::fmt::Display for Polynomial {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let terms = self
+            .coefficients
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(_, coefficient)| coefficient.numerator != 0)
+            .collect::<Vec<_>>();
+        if terms.is_empty() {
+            return write!(f, "0");
+        }
+        for (index, (power, coefficient)) in terms.into_iter().enumerate() {
+            let sign = coefficient.numerator < 0;
+            match (index, sign) {
+                (0, true) => write!(f, "-")?,
+                (0, false) => {}
+                (_, true) => write!(f, " - ")?,
+                (_, false) => write!(f, " + ")?,
+            }
+            let magnitude = Rational::new(coefficient.numerator.abs(), coefficient.denominator);
+            match (power, magnitude.denominator) {
+                (0, _) => write!(f, "{}", magnitude)?,
+                (_, 1) if magnitude.numerator == 1 => {}
+                (_, 1) => write!(f, "{}", magnitude)?,
+                _ => write!(f, "({})", magnitude)?,
+            }
+            match power {
+                0 => {}
+                1 => write!(f, "x")?,
+                _ => write!(f, "x^{}", power)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Consistency {
+    Consistent { degree: usize },
+    // every value is needed to pin the polynomial down, none is left to confirm it
+    Unreliable { degree: usize, len: usize },
+}
+
+fn check_history(values: &[i128]) -> Result<Consistency, SequenceError> {
+    if values.is_empty() {
+        return Err(SequenceError::Empty);
+    }
+    let degree = Polynomial::interpolate(values)?.degree();
+    match degree + 1 < values.len() {
+        true => Ok(Consistency::Consistent { degree }),
+        false => Ok(Consistency::Unreliable {
+            degree,
+            len: values.len(),
+        }),
+    }
+}
+
+fn parse_history(line: &str) -> Result<Vec<i128>, SequenceError> {
+    line.split_ascii_whitespace()
+        .map(|number| {
+            number
+                .parse::<i128>()
+                .map_err(|_| SequenceError::InvalidNumber(number.to_string()))
+        })
+        .collect()
+}
+
+fn parse_input(input: &str) -> Vec<DifferenceTable> {
     input
         .lines()
-        .map(|line| {
-            line.split_ascii_whitespace()
-                .fold((0, 1, Vec::new()), |(sum, sign, diffs), number| {
-                    let mut number = number.parse::<i64>().expect("Not a i64 number");
-                    let mut new_diffs = Vec::new();
-                    for diff in &diffs {
-                        new_diffs.push(number);
-                        number -= diff;
-                    }
-                    new_diffs.push(number);
-                    (sum + (sign * number), -sign, new_diffs)
-                })
+        .enumerate()
+        .map(|(index, line)| {
+            parse_history(line)
+                .and_then(|values| DifferenceTable::new(&values))
+                .unwrap_or_else(|err| panic!("Wrong history on line {}: {:?}", index + 1, err))
         })
-        .map(|(sum, _, _)| sum)
+        .collect()
+}
+
+fn extrapolate(
+    input: &str,
+    value: impl Fn(&DifferenceTable) -> Result<i128, SequenceError>,
+) -> i128 {
+    parse_input(input)
+        .iter()
+        .map(|table| value(table).expect("Extrapolated value doesn't fit into i128"))
         .sum()
 }
 
+fn first_part(input: &str) -> i128 {
+    extrapolate(input, |table| table.forward(1))
+}
+
+fn second_part(input: &str) -> i128 {
+    extrapolate(input, |table| table.backward(1))
+}
+
 fn main() {
     let input = include_str!("../inputs/input.txt");
     let first_part = first_part(input);
     println!("First part: {}", first_part);
     let second_part = second_part(input);
     println!("Second part: {}", second_part);
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if let Some(steps) = args.iter().find_map(|arg| arg.strip_prefix("steps=")) {
+        let steps = steps.parse::<u64>().expect("Steps is not a number");
+        let forward = extrapolate(input, |table| table.forward(steps));
+        let backward = extrapolate(input, |table| table.backward(steps));
+        println!(
+            "{} steps forward: {}, backward: {}",
+            steps, forward, backward
+        );
+    }
+    if args.iter().any(|arg| arg == "formula") {
+        for (index, table) in parse_input(input).iter().enumerate() {
+            let polynomial =
+                Polynomial::from_table(table).expect("Polynomial doesn't fit into i128");
+            println!("Line {}: f(x) = {}", index + 1, polynomial);
+        }
+    }
+    if let Some(x) = args.iter().find_map(|arg| arg.strip_prefix("at=")) {
+        let x = x.parse::<i128>().expect("Point is not a number");
+        for (index, table) in parse_input(input).iter().enumerate() {
+            let polynomial =
+                Polynomial::from_table(table).expect("Polynomial doesn't fit into i128");
+            let value = polynomial.evaluate(x).expect("Value doesn't fit into i128");
+            println!("Line {}: f({}) = {}", index + 1, x, value);
+        }
+    }
+    if args.iter().any(|arg| arg == "check") {
+        for (index, line) in input.lines().enumerate() {
+            let values = parse_history(line).expect("Wrong history");
+            match check_history(&values) {
+                Ok(Consistency::Consistent { .. }) => {}
+                result => println!("Line {}: {:?}", index + 1, result),
+            }
+        }
+    }
+    if args.iter().any(|arg| arg == "degrees") {
+        for (index, table) in parse_input(input).iter().enumerate() {
+            println!("Line {}: degree {}", index + 1, table.degree());
+        }
+    }
 }
 
 #[cfg(test)]
@@ -81,4 +380,123 @@ mod tests {
         let result = second_part(data);
         assert_eq!(result, 993);
     }
+
+    #[test]
+    fn test_degrees() {
+        let data = include_str!("../inputs/test.txt");
+        let degrees = parse_input(data)
+            .iter()
+            .map(|t| t.degree())
+            .collect::<Vec<_>>();
+        assert_eq!(degrees, vec![1, 2, 3]);
+        assert_eq!(DifferenceTable::new(&[7, 7, 7]).unwrap().degree(), 0);
+        assert_eq!(DifferenceTable::new(&[0, 0]).unwrap().degree(), 0);
+    }
+
+    #[test]
+    fn test_extrapolate_many_steps() {
+        let squares = (0..5).map(|n: i128| n * n).collect::<Vec<_>>();
+        let table = DifferenceTable::new(&squares).unwrap();
+        assert_eq!(table.degree(), 2);
+        assert_eq!(table.forward(1), Ok(25));
+        assert_eq!(table.forward(10), Ok(196));
+        assert_eq!(table.backward(3), Ok(9));
+        assert_eq!(table.value_at(2), Ok(4));
+        let cubes = [-8, -1, 0, 1, 8, 27];
+        let table = DifferenceTable::new(&cubes).unwrap();
+        assert_eq!(table.backward(7), Ok(-(9i128.pow(3))));
+        assert_eq!(table.forward(1000), Ok(1003i128.pow(3)));
+    }
+
+    #[test]
+    fn test_sequence_errors() {
+        assert_eq!(
+            DifferenceTable::new(&[1, 2, 4, 8]).err(),
+            Some(SequenceError::NoConstantRow)
+        );
+        assert_eq!(
+            DifferenceTable::new(&[5]).err(),
+            Some(SequenceError::NoConstantRow)
+        );
+        assert_eq!(DifferenceTable::new(&[]).err(), Some(SequenceError::Empty));
+        assert_eq!(
+            DifferenceTable::new(&[i128::MIN, i128::MAX]).err(),
+            Some(SequenceError::Overflow)
+        );
+        assert_eq!(
+            parse_history("1 2 x").err(),
+            Some(SequenceError::InvalidNumber("x".to_string()))
+        );
+        let table = DifferenceTable::new(&[0, 1 << 100, 1 << 101]).unwrap();
+        assert_eq!(table.forward(1 << 40), Err(SequenceError::Overflow));
+    }
+
+    #[test]
+    fn test_polynomial_formula() {
+        let data = include_str!("../inputs/test.txt");
+        let formulas = parse_input(data)
+            .iter()
+            .map(|table| Polynomial::from_table(table).unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            formulas,
+            vec![
+                "3x",
+                "(1/2)x^2 + (3/2)x + 1",
+                "(1/3)x^3 - x^2 + (11/3)x + 10"
+            ]
+        );
+        let negative = Polynomial::interpolate(&[-1, -2, -5]).unwrap();
+        assert_eq!(negative.to_string(), "-x^2 - 1");
+        assert_eq!(Polynomial::interpolate(&[0, 0]).unwrap().to_string(), "0");
+    }
+
+    #[test]
+    fn test_polynomial_evaluate() {
+        let data = include_str!("../inputs/test.txt");
+        for table in parse_input(data) {
+            let polynomial = Polynomial::from_table(&table).unwrap();
+            for x in -20..20 {
+                let value = Rational::integer(table.value_at(x).unwrap());
+                assert_eq!(polynomial.evaluate(x), Ok(value));
+            }
+        }
+        let half = Polynomial {
+            coefficients: vec![Rational::integer(0), Rational::new(1, 2)],
+        };
+        assert_eq!(half.evaluate(3), Ok(Rational::new(3, 2)));
+        assert_eq!(Rational::new(4, -6).to_string(), "-2/3");
+    }
+
+    #[test]
+    fn test_check_history() {
+        let data = include_str!("../inputs/test.txt");
+        for line in data.lines() {
+            let values = parse_history(line).unwrap();
+            let table = DifferenceTable::new(&values).unwrap();
+            assert_eq!(
+                Polynomial::interpolate(&values),
+                Polynomial::from_table(&table)
+            );
+            assert_eq!(
+                check_history(&values),
+                Ok(Consistency::Consistent {
+                    degree: table.degree()
+                })
+            );
+        }
+        assert_eq!(
+            check_history(&[1, 2, 4, 8]),
+            Ok(Consistency::Unreliable { degree: 3, len: 4 })
+        );
+        assert_eq!(
+            check_history(&[1, 2, 4]),
+            Ok(Consistency::Unreliable { degree: 2, len: 3 })
+        );
+        assert_eq!(
+            check_history(&[1, 2, 4, 7]),
+            Ok(Consistency::Consistent { degree: 2 })
+        );
+        assert_eq!(check_history(&[]), Err(SequenceError::Empty));
+    }
 }
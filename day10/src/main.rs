@@ -3,7 +3,7 @@ use std::{
     ops::Add,
 };
 
-#[derive(PartialEq)]
+#[derive(Clone, Copy, PartialEq, Debug)]
 enum Pipe {
     None,       // .
     Start,      // S
@@ -47,7 +47,37 @@ impl From<char> for Pipe {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+const PIPES: [Pipe; 6] = [
+    Pipe::Vertical,
+    Pipe::Horizontal,
+    Pipe::DownRight,
+    Pipe::DownLeft,
+    Pipe::UpLeft,
+    Pipe::UpRight,
+];
+
+impl Pipe {
+    fn connects(&self, direction: Direction) -> bool {
+        matches!(
+            (self, direction),
+            (Pipe::Vertical | Pipe::UpLeft | Pipe::UpRight, Direction::Up)
+                | (
+                    Pipe::Vertical | Pipe::DownLeft | Pipe::DownRight,
+                    Direction::Down
+                )
+                | (
+                    Pipe::Horizontal | Pipe::UpLeft | Pipe::DownLeft,
+                    Direction::Left
+                )
+                | (
+                    Pipe::Horizontal | Pipe::UpRight | Pipe::DownRight,
+                    Direction::Right
+                )
+        )
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
 enum Direction {
     Up,
     Left,
@@ -55,6 +85,24 @@ enum Direction {
     Right,
 }
 
+const DIRECTIONS: [Direction; 4] = [
+    Direction::Up,
+    Direction::Left,
+    Direction::Down,
+    Direction::Right,
+];
+
+impl Direction {
+    fn opposite(self) -> Direction {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Left => Direction::Right,
+            Direction::Down => Direction::Up,
+            Direction::Right => Direction::Left,
+        }
+    }
+}
+
 impl From<Direction> for (isize, isize) {
     fn from(val: Direction) -> Self {
         match val {
@@ -100,12 +148,78 @@ fn get_new_direction(pipe: &Pipe, old_direction: Direction) -> Option<Direction>
     }
 }
 
-struct Board {
+#[derive(Debug, PartialEq)]
+enum MazeError {
+    NoLoop,
+    AmbiguousStart(Vec<Pipe>),
+    Disagreement {
+        shoelace: usize,
+        ray_cast: usize,
+        flood_fill: usize,
+    },
+}
+
+// Tiles of the main loop in walking order starting at `S`, together with the
+// pipe that `S` stands for.
+struct MainLoop {
+    start_pipe: Pipe,
+    polygon: Vec<Position>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Tile {
+    Inside,
+    Outside,
+    Loop,
+    Junk { inside: bool },
+}
+
+impl Display for Tile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let c = match self {
+            Tile::Inside => "I",
+            Tile::Outside => "O",
+            Tile::Loop => "#",
+            Tile::Junk { inside: true } => "i",
+            Tile::Junk { inside: false } => "o",
+        };
+        write!(f, "{}", c)
+    }
+}
+
+struct Classification {
+    tiles: Vec<Vec<Tile>>,
+}
+
+impl Display for Classification {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for line in &self.tiles {
+            for tile in line {
+                write!(f, "{}", tile)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl Classification {
+    // Pipes that are not part of the main loop count as enclosed tiles too.
+    fn enclosed_tiles(&self) -> usize {
+        self.tiles
+            .iter()
+            .flatten()
+            .filter(|tile| matches!(tile, Tile::Inside | Tile::Junk { inside: true }))
+            .count()
+    }
+}
+
+struct PipeMaze {
     pipes: Vec<Vec<Pipe>>,
     start: Position,
 }
 
-impl Display for Board {
+impl Display for PipeMaze {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         for line in &self.pipes {
             for pipe in line {
@@ -117,188 +231,235 @@ impl Display for Board {
     }
 }
 
-fn get_start_direction(board: &Board) -> Direction {
-    let direction = Direction::Up;
-    let position = &board.start + direction;
-    if let Some(position) = position {
-        if let Pipe::DownLeft | Pipe::Vertical | Pipe::DownRight =
-            board.pipes[position.y][position.x]
-        {
-            return direction;
-        }
+impl PipeMaze {
+    fn parse(input: &str) -> PipeMaze {
+        let mut start = None;
+        let pipes = input
+            .lines()
+            .enumerate()
+            .map(|(y, line)| {
+                line.chars()
+                    .enumerate()
+                    .map(|(x, c)| {
+                        let pipe = c.into();
+                        if let Pipe::Start = pipe {
+                            start = Some(Position { x, y });
+                        }
+                        pipe
+                    })
+                    .collect()
+            })
+            .collect();
+        let start = start.expect("Unable to find start position");
+        PipeMaze { pipes, start }
     }
 
-    let direction = Direction::Left;
-    let position = &board.start + direction;
-    if let Some(position) = position {
-        if let Pipe::UpRight | Pipe::Horizontal | Pipe::DownRight =
-            board.pipes[position.y][position.x]
-        {
-            return direction;
-        }
+    // Lines may be ragged, grids over the whole maze use the widest one.
+    fn width(&self) -> usize {
+        self.pipes.iter().map(|line| line.len()).max().unwrap_or(0)
+    }
+
+    fn pipe(&self, position: &Position) -> Option<Pipe> {
+        self.pipes.get(position.y)?.get(position.x).copied()
+    }
+
+    // Pipes that could hide under `S`, both of their ends lead to a neighbour
+    // connected back to the start.
+    fn start_candidates(&self) -> Vec<Pipe> {
+        let connected = |direction: Direction| {
+            (&self.start + direction)
+                .and_then(|position| self.pipe(&position))
+                .is_some_and(|pipe| pipe.connects(direction.opposite()))
+        };
+        PIPES
+            .into_iter()
+            .filter(|pipe| {
+                DIRECTIONS
+                    .iter()
+                    .all(|&direction| !pipe.connects(direction) || connected(direction))
+            })
+            .collect()
     }
 
-    let direction = Direction::Down;
-    let position = &board.start + direction;
-    if let Some(position) = position {
-        if let Pipe::UpLeft | Pipe::Vertical | Pipe::UpRight = board.pipes[position.y][position.x] {
-            return direction;
+    // Walks out of `S` through one end of `start_pipe`, the loop is closed only
+    // when the walk comes back through the other end.
+    fn walk_loop(&self, start_pipe: Pipe) -> Option<Vec<Position>> {
+        let mut direction = *DIRECTIONS
+            .iter()
+            .find(|&&direction| start_pipe.connects(direction))?;
+        let mut position = self.start.clone();
+        let mut polygon = Vec::new();
+        let max_steps = self.pipes.iter().map(|line| line.len()).sum::<usize>();
+        loop {
+            polygon.push(position.clone());
+            position = (&position + direction)?;
+            let pipe = self.pipe(&position)?;
+            if pipe == Pipe::Start {
+                return start_pipe.connects(direction.opposite()).then_some(polygon);
+            }
+            direction = get_new_direction(&pipe, direction)?;
+            if polygon.len() >= max_steps {
+                return None;
+            }
         }
     }
 
-    let direction = Direction::Right;
-    let position = &board.start + direction;
-    if let Some(position) = position {
-        if let Pipe::UpLeft | Pipe::Horizontal | Pipe::DownLeft =
-            board.pipes[position.y][position.x]
-        {
-            return direction;
+    fn main_loop(&self) -> Result<MainLoop, MazeError> {
+        let mut loops = self
+            .start_candidates()
+            .into_iter()
+            .filter_map(|start_pipe| {
+                let polygon = self.walk_loop(start_pipe)?;
+                Some(MainLoop {
+                    start_pipe,
+                    polygon,
+                })
+            })
+            .collect::<Vec<_>>();
+        match loops.len() {
+            0 => Err(MazeError::NoLoop),
+            1 => Ok(loops.remove(0)),
+            _ => Err(MazeError::AmbiguousStart(
+                loops.iter().map(|main_loop| main_loop.start_pipe).collect(),
+            )),
         }
     }
 
-    panic!("Unable to get start direction");
-}
+    // Shoelace formula gives the area of the loop polygon, Pick's theorem
+    // (A = I + B / 2 - 1) then turns it into the number of tiles strictly inside.
+    fn enclosed_tiles(&self, main_loop: &MainLoop) -> usize {
+        let polygon = &main_loop.polygon;
+        let double_area = polygon
+            .iter()
+            .zip(polygon.iter().cycle().skip(1))
+            .map(|(a, b)| a.x as i64 * b.y as i64 - b.x as i64 * a.y as i64)
+            .sum::<i64>()
+            .unsigned_abs() as usize;
+        (double_area + 2 - polygon.len()) / 2
+    }
 
-fn first_part(input: &str) -> usize {
-    let board = get_board_with_start(input);
-    let mut direction = get_start_direction(&board);
-    let mut position = board.start;
-    let mut steps = 0usize;
-    let max_steps = board.pipes.len() * board.pipes[0].len();
-    loop {
-        steps += 1;
-        position = (&position + direction).unwrap();
-        let pipe = &board.pipes[position.y][position.x];
-        if pipe == &Pipe::Start {
-            break;
+    // Walks every row and flips inside/outside on each loop tile that leads up.
+    fn enclosed_tiles_by_ray_cast(&self, main_loop: &MainLoop) -> usize {
+        let mut on_loop = vec![vec![false; self.width()]; self.pipes.len()];
+        for position in &main_loop.polygon {
+            on_loop[position.y][position.x] = true;
         }
-        direction = get_new_direction(pipe, direction).expect("Can't get next direction");
-        if steps >= max_steps {
-            panic!("Loop too large")
+        self.pipes
+            .iter()
+            .enumerate()
+            .map(|(y, line)| {
+                let mut inside = false;
+                line.iter()
+                    .enumerate()
+                    .filter(|&(x, pipe)| {
+                        if !on_loop[y][x] {
+                            return inside;
+                        }
+                        let pipe = match pipe {
+                            Pipe::Start => main_loop.start_pipe,
+                            pipe => *pipe,
+                        };
+                        if pipe.connects(Direction::Up) {
+                            inside = !inside;
+                        }
+                        false
+                    })
+                    .count()
+            })
+            .sum()
+    }
+
+    // Every tile becomes 3x3 cells with the loop drawn through the middle, so
+    // the outside can squeeze between two neighbouring pipes. Flood fill from
+    // the border then reaches exactly the middle cells of outside tiles.
+    fn classify(&self, main_loop: &MainLoop) -> Classification {
+        let height = self.pipes.len();
+        let width = self.width();
+        let mut on_loop = vec![vec![false; width]; height];
+        let mut blocked = vec![vec![false; width * 3]; height * 3];
+        for position in &main_loop.polygon {
+            on_loop[position.y][position.x] = true;
+            let pipe = match self.pipe(position) {
+                Some(Pipe::Start) => main_loop.start_pipe,
+                pipe => pipe.unwrap(),
+            };
+            let center = Position {
+                x: position.x * 3 + 1,
+                y: position.y * 3 + 1,
+            };
+            blocked[center.y][center.x] = true;
+            for direction in DIRECTIONS.into_iter().filter(|&d| pipe.connects(d)) {
+                let arm = (&center + direction).unwrap();
+                blocked[arm.y][arm.x] = true;
+            }
         }
+        let mut reached = vec![vec![false; width * 3]; height * 3];
+        let mut stack = (0..height * 3)
+            .flat_map(|y| [(0, y), (width * 3 - 1, y)])
+            .chain((0..width * 3).flat_map(|x| [(x, 0), (x, height * 3 - 1)]))
+            .map(|(x, y)| Position { x, y })
+            .collect::<Vec<_>>();
+        while let Some(position) = stack.pop() {
+            if blocked[position.y][position.x] || reached[position.y][position.x] {
+                continue;
+            }
+            reached[position.y][position.x] = true;
+            for direction in DIRECTIONS {
+                if let Some(next) = &position + direction {
+                    if next.x < width * 3 && next.y < height * 3 {
+                        stack.push(next);
+                    }
+                }
+            }
+        }
+        let tiles = (0..height)
+            .map(|y| {
+                (0..width)
+                    .map(|x| {
+                        let inside = !reached[y * 3 + 1][x * 3 + 1];
+                        let position = Position { x, y };
+                        match (on_loop[y][x], self.pipe(&position), inside) {
+                            (true, _, _) => Tile::Loop,
+                            (_, Some(Pipe::None) | None, true) => Tile::Inside,
+                            (_, Some(Pipe::None) | None, false) => Tile::Outside,
+                            (_, _, inside) => Tile::Junk { inside },
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        Classification { tiles }
     }
-    steps.div_ceil(2)
 }
 
-#[derive(Clone, PartialEq)]
-enum Tile {
-    Unknown,
-    Inside,
-    Outside,
-    Pipe,
+fn find_main_loop(maze: &PipeMaze) -> MainLoop {
+    maze.main_loop()
+        .unwrap_or_else(|err| panic!("Can't find main loop: {:?}", err))
 }
 
-impl Debug for Tile {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::Unknown => write!(f, "!"),
-            Self::Inside => write!(f, "I"),
-            Self::Outside => write!(f, "."),
-            Self::Pipe => write!(f, "-"),
-        }
-    }
+fn first_part(input: &str) -> usize {
+    let maze = PipeMaze::parse(input);
+    find_main_loop(&maze).polygon.len() / 2
 }
 
 fn second_part(input: &str) -> usize {
-    let mut board = get_board_with_start(input);
-    let mut tile_map = vec![vec![Tile::Unknown; board.pipes[0].len()]; board.pipes.len()];
-    let start_direction = get_start_direction(&board);
-    let mut direction = start_direction;
-    let mut position = board.start.clone();
-    let mut steps = 0usize;
-    let max_steps = board.pipes.len() * board.pipes[0].len();
-    loop {
-        steps += 1;
-        position = (&position + direction).unwrap();
-        let pipe = &board.pipes[position.y][position.x];
-        tile_map[position.y][position.x] = Tile::Pipe;
-        if pipe == &Pipe::Start {
-            break;
-        }
-        direction = get_new_direction(pipe, direction).expect("Can't get next direction");
-        if steps >= max_steps {
-            panic!("Loop too large")
-        }
-    }
-
-    board.pipes[board.start.y][board.start.x] = match (start_direction, direction) {
-        (Direction::Up, Direction::Up) => Pipe::Vertical,
-        (Direction::Up, Direction::Left) => Pipe::UpRight,
-        (Direction::Up, Direction::Right) => Pipe::UpLeft,
-        (Direction::Left, Direction::Up) => Pipe::DownLeft,
-        (Direction::Left, Direction::Left) => Pipe::Horizontal,
-        (Direction::Left, Direction::Down) => Pipe::UpLeft,
-        (Direction::Down, Direction::Left) => Pipe::DownRight,
-        (Direction::Down, Direction::Down) => Pipe::Vertical,
-        (Direction::Down, Direction::Right) => Pipe::DownLeft,
-        (Direction::Right, Direction::Up) => Pipe::DownRight,
-        (Direction::Right, Direction::Down) => Pipe::UpRight,
-        (Direction::Right, Direction::Right) => Pipe::Horizontal,
-        _ => panic!("Imposible"),
-    };
-
-    for (y, line) in board.pipes.iter_mut().enumerate() {
-        (0..line.len()).for_each(|x| {
-            if let Tile::Unknown = tile_map[y][x] {
-                line[x] = Pipe::None;
-            }
-        })
-    }
-
-    for (y, line) in tile_map.iter_mut().enumerate() {
-        let mut odd_number_pipes = false;
-        (0..line.len()).for_each(|x| {
-            (line[x], odd_number_pipes) = match (&board.pipes[y][x], odd_number_pipes) {
-                (Pipe::None, true) => (Tile::Inside, true),
-                (Pipe::None, false) => (Tile::Outside, false),
-                (Pipe::Vertical, odd) => (Tile::Pipe, !odd),
-                (Pipe::UpLeft, odd) => (Tile::Pipe, !odd),
-                (Pipe::UpRight, odd) => (Tile::Pipe, !odd),
-                (_, odd) => (Tile::Pipe, odd),
-            }
-        });
-    }
-
-    tile_map
-        .iter()
-        .enumerate()
-        .map(|(y, line)| {
-            line.iter()
-                .enumerate()
-                .map(|(x, tile)| match (tile, &board.pipes[y][x]) {
-                    (Tile::Inside, Pipe::None) => 1,
-                    _ => 0,
-                })
-                .sum::<usize>()
-        })
-        .sum()
+    let maze = PipeMaze::parse(input);
+    let main_loop = find_main_loop(&maze);
+    maze.enclosed_tiles(&main_loop)
 }
 
-fn get_board_with_start(input: &str) -> Board {
-    let mut start = None;
-    let pipes = input
-        .lines()
-        .enumerate()
-        .map(|(y, line)| {
-            line.chars()
-                .enumerate()
-                .map(|(x, c)| {
-                    let pipe = c.into();
-                    if let Pipe::Start = pipe {
-                        start = Some((x, y));
-                    }
-                    pipe
-                })
-                .collect()
-        })
-        .collect();
-    let start = start.expect("Unable to find start position");
-    Board {
-        pipes,
-        start: Position {
-            x: start.0,
-            y: start.1,
-        },
+// Counts the enclosed tiles with all three methods, they have to agree.
+fn cross_check(maze: &PipeMaze, main_loop: &MainLoop) -> Result<usize, MazeError> {
+    let shoelace = maze.enclosed_tiles(main_loop);
+    let ray_cast = maze.enclosed_tiles_by_ray_cast(main_loop);
+    let flood_fill = maze.classify(main_loop).enclosed_tiles();
+    match shoelace == ray_cast && shoelace == flood_fill {
+        true => Ok(shoelace),
+        false => Err(MazeError::Disagreement {
+            shoelace,
+            ray_cast,
+            flood_fill,
+        }),
     }
 }
 
@@ -308,6 +469,15 @@ fn main() {
     println!("First part: {}", first_part);
     let second_part = second_part(input);
     println!("Second part: {}", second_part);
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let maze = PipeMaze::parse(input);
+    let main_loop = find_main_loop(&maze);
+    if args.iter().any(|arg| arg == "check") {
+        println!("Cross-check: {:?}", cross_check(&maze, &main_loop));
+    }
+    if args.iter().any(|arg| arg == "classify") {
+        print!("{}", maze.classify(&main_loop));
+    }
 }
 
 #[cfg(test)]
@@ -352,6 +522,103 @@ mod tests {
         assert_eq!(result, 429);
     }
 
+    #[test]
+    fn test_main_loop_polygon() {
+        let data = include_str!("../inputs/test.txt");
+        let maze = PipeMaze::parse(data);
+        assert_eq!(maze.start_candidates(), vec![Pipe::DownRight]);
+        let main_loop = maze.main_loop().unwrap();
+        assert_eq!(main_loop.start_pipe, Pipe::DownRight);
+        let polygon = &main_loop.polygon;
+        assert_eq!(polygon.len(), 16);
+        assert_eq!(polygon[0], Position { x: 0, y: 2 });
+        assert_eq!(polygon[1], Position { x: 0, y: 3 });
+        assert_eq!(polygon[15], Position { x: 1, y: 2 });
+        assert_eq!(maze.enclosed_tiles(&main_loop), 1);
+        assert_eq!(maze.enclosed_tiles_by_ray_cast(&main_loop), 1);
+    }
+
+    #[test]
+    fn test_enclosed_tiles_cross_check() {
+        for data in [
+            include_str!("../inputs/test_second.txt"),
+            include_str!("../inputs/test_second_1.txt"),
+            "S7\nLJ",
+            ".....\n.F-7.\n.|.S.\n.L-J.\n.....",
+            ".\nS-7\n|.|\nL-J",
+            "F-7\n|.|..\nS-J.\n",
+        ] {
+            let maze = PipeMaze::parse(data);
+            let main_loop = maze.main_loop().unwrap();
+            assert_eq!(
+                cross_check(&maze, &main_loop),
+                Ok(maze.enclosed_tiles(&main_loop))
+            );
+        }
+        let maze = PipeMaze::parse(".....\n.F-7.\n.|.S.\n.L-J.\n.....");
+        let main_loop = maze.main_loop().unwrap();
+        assert_eq!(main_loop.start_pipe, Pipe::Vertical);
+        assert_eq!(maze.enclosed_tiles(&main_loop), 1);
+    }
+
+    #[test]
+    fn test_start_with_three_connected_neighbours() {
+        let maze = PipeMaze::parse("F-7\n|.|\nS-J\n|..\nL..");
+        assert_eq!(
+            maze.start_candidates(),
+            vec![Pipe::Vertical, Pipe::DownRight, Pipe::UpRight]
+        );
+        let main_loop = maze.main_loop().unwrap();
+        assert_eq!(main_loop.start_pipe, Pipe::UpRight);
+        assert_eq!(main_loop.polygon.len(), 8);
+        assert_eq!(maze.enclosed_tiles_by_ray_cast(&main_loop), 1);
+    }
+
+    #[test]
+    fn test_start_errors() {
+        let maze = PipeMaze::parse("F-7..\n|.|..\nL-S-7\n..|.|\n..L-J");
+        assert_eq!(
+            maze.main_loop().err(),
+            Some(MazeError::AmbiguousStart(vec![
+                Pipe::DownRight,
+                Pipe::UpLeft
+            ]))
+        );
+        for data in ["S", "S--\n...", "S7\n|J", "-S-\n..."] {
+            let maze = PipeMaze::parse(data);
+            assert_eq!(maze.main_loop().err(), Some(MazeError::NoLoop));
+        }
+    }
+
+    #[test]
+    fn test_classification_squeezes_between_pipes() {
+        let data = include_str!("../inputs/test_second_1.txt");
+        let maze = PipeMaze::parse(data);
+        let classification = maze.classify(&maze.main_loop().unwrap());
+        let expected = "OOOOOOOOOOO\n\
+                        O#########O\n\
+                        O#########O\n\
+                        O##OOOOO##O\n\
+                        O##OOOOO##O\n\
+                        O####O####O\n\
+                        O#II#O#II#O\n\
+                        O####O####O\n\
+                        OOOOOOOOOOO\n";
+        assert_eq!(classification.to_string(), expected);
+        assert_eq!(classification.enclosed_tiles(), 4);
+    }
+
+    #[test]
+    fn test_classification_junk_inside() {
+        let maze = PipeMaze::parse("S---7\n|F7.|\n|LJ.|\nL---J\n-....");
+        let classification = maze.classify(&maze.main_loop().unwrap());
+        assert_eq!(classification.tiles[1][1], Tile::Junk { inside: true });
+        assert_eq!(classification.tiles[1][3], Tile::Inside);
+        assert_eq!(classification.tiles[4][0], Tile::Junk { inside: false });
+        assert_eq!(classification.tiles[4][1], Tile::Outside);
+        assert_eq!(classification.enclosed_tiles(), 6);
+    }
+
     #[test]
     fn test_add_position_and_direction() {
         let position = Position { x: 5, y: 5 };